@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A 48-bit Bluetooth device address (BD_ADDR), most significant byte first.
+/// Displayed as `00:11:22:33:44:55`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BluetoothAddress([u8; 6]);
+
+impl BluetoothAddress {
+    pub fn new(bytes: [u8; 6]) -> Result<Self> {
+        if bytes == [0; 6] || bytes == [0xFF; 6] {
+            return Err(anyhow!("{} is not a device address", Self(bytes)));
+        }
+        Ok(Self(bytes))
+    }
+
+    pub fn bytes(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// From the integer form used by WinRT `BluetoothAddress` and Winsock
+    /// `BTH_ADDR`.
+    pub fn from_u64(value: u64) -> Result<Self> {
+        if value >> 48 != 0 {
+            return Err(anyhow!("{:#x} is wider than 48 bits", value));
+        }
+        let bytes = value.to_be_bytes();
+        Self::new([bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]])
+    }
+
+    pub fn to_u64(&self) -> u64 {
+        self.0.iter().fold(0, |acc, byte| acc << 8 | *byte as u64)
+    }
+
+    /// From a PnP device instance ID, e.g.
+    /// `BTHENUM\{0000111e-...}_LOCALMFG&0002\7&2A7F5A58&0&001122334455_C00000000`,
+    /// `BTHENUM\DEV_001122334455\...` or `BTHLE\DEV_001122334455\...`.
+    /// Only Bluetooth enumerators are considered, as a USB radio's serial
+    /// number can look like an address too.
+    pub fn from_pnp_instance_id(instance_id: &str) -> Option<Self> {
+        let segments: Vec<&str> = instance_id.split('\\').collect();
+        if !segments[0].to_ascii_uppercase().starts_with("BTH") {
+            return None;
+        }
+        if let Some(hex) = segments.iter().find_map(|segment| {
+            segment
+                .get(..4)
+                .filter(|prefix| prefix.eq_ignore_ascii_case("DEV_"))
+                .and_then(|_| segment.get(4..16))
+        }) {
+            return Self::from_hex(hex);
+        }
+
+        // Otherwise the address is a 12 digit token of the instance part
+        segments
+            .iter()
+            .skip(1)
+            .flat_map(|segment| segment.split(['&', '_']))
+            .find_map(Self::from_hex)
+    }
+
+    /// From a BlueZ object path, e.g. `/org/bluez/hci0/dev_00_11_22_33_44_55`.
+    pub fn from_bluez_path(path: &str) -> Option<Self> {
+        let node = path.rsplit('/').next()?.strip_prefix("dev_")?;
+        let octets: Vec<&str> = node.split('_').collect();
+        if octets.len() != 6 || octets.iter().any(|octet| octet.len() != 2) {
+            return None;
+        }
+        Self::from_hex(&octets.concat())
+    }
+
+    /// Exactly 12 hex digits.
+    fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Self::from_u64(u64::from_str_radix(hex, 16).ok()?).ok()
+    }
+}
+
+impl FromStr for BluetoothAddress {
+    type Err = anyhow::Error;
+
+    /// Accepts `00:11:22:33:44:55`, `00-11-22-33-44-55` and `001122334455`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || anyhow!("Invalid Bluetooth address '{}'", s);
+
+        let hex = if s.len() == 12 {
+            s.to_string()
+        } else {
+            let separator = if s.contains(':') { ':' } else { '-' };
+            let octets: Vec<&str> = s.split(separator).collect();
+            if octets.len() != 6 || octets.iter().any(|octet| octet.len() != 2) {
+                return Err(invalid());
+            }
+            octets.concat()
+        };
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        Self::from_u64(u64::from_str_radix(&hex, 16).map_err(|_| invalid())?)
+    }
+}
+
+impl fmt::Display for BluetoothAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", a, b, c, d, e, g)
+    }
+}
+
+impl Serialize for BluetoothAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BluetoothAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let address: BluetoothAddress = "00:1a:7d:da:71:13".parse().unwrap();
+        assert_eq!(address.to_string(), "00:1A:7D:DA:71:13");
+        assert_eq!(address.to_u64(), 0x001A7DDA7113);
+        assert_eq!("00-1A-7D-DA-71-13".parse::<BluetoothAddress>().unwrap(), address);
+        assert_eq!("001A7DDA7113".parse::<BluetoothAddress>().unwrap(), address);
+        assert_eq!(BluetoothAddress::from_u64(0x001A7DDA7113).unwrap(), address);
+
+        for invalid in ["", "00:1A:7D:DA:71", "00:1A:7D:DA:71:1G", "00:00:00:00:00:00", "0:1A:7D:DA:71:130"] {
+            assert!(invalid.parse::<BluetoothAddress>().is_err(), "{}", invalid);
+        }
+        assert!(BluetoothAddress::from_u64(1 << 48).is_err());
+    }
+
+    #[test]
+    fn test_from_pnp_instance_id() {
+        let expected = Some("00:11:22:33:44:55".parse().unwrap());
+        assert_eq!(BluetoothAddress::from_pnp_instance_id(r"BTHENUM\DEV_001122334455\7&2A7F5A58&0&BLUETOOTHDEVICE_001122334455"), expected);
+        assert_eq!(BluetoothAddress::from_pnp_instance_id(r"BTHLE\DEV_001122334455\8&1B6D7B22&0&001122334455"), expected);
+        assert_eq!(
+            BluetoothAddress::from_pnp_instance_id(
+                r"BTHENUM\{0000111E-0000-1000-8000-00805F9B34FB}_LOCALMFG&0002\7&2A7F5A58&0&001122334455_C00000000"
+            ),
+            expected
+        );
+        // The radio's own enumerators carry no device address
+        assert_eq!(
+            BluetoothAddress::from_pnp_instance_id(r"BTHENUM\{0000110B-0000-1000-8000-00805F9B34FB}_LOCALMFG&0000\7&2A7F5A58&0&000000000000_00000000"),
+            None
+        );
+        assert_eq!(BluetoothAddress::from_pnp_instance_id(r"USB\VID_0A12&PID_0001\5CF3708A1B2C"), None);
+    }
+
+    #[test]
+    fn test_from_bluez_path() {
+        assert_eq!(
+            BluetoothAddress::from_bluez_path("/org/bluez/hci0/dev_00_1A_7D_DA_71_13").map(|a| a.to_string()),
+            Some("00:1A:7D:DA:71:13".to_string())
+        );
+        assert_eq!(BluetoothAddress::from_bluez_path("/org/bluez/hci0"), None);
+    }
+}
@@ -0,0 +1,17 @@
+use crate::address::BluetoothAddress;
+
+/// The integer form of `mac_str`, as Winsock and WinRT take it.
+pub fn parse_mac_address(mac_str: &str) -> Result<u64, anyhow::Error> {
+    Ok(mac_str.parse::<BluetoothAddress>()?.to_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mac_parsing() {
+        let addr = parse_mac_address("00:11:22:33:44:55").unwrap();
+        assert_eq!(addr, 0x001122334455);
+    }
+}
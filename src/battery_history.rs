@@ -0,0 +1,291 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bluetooth_battery::BatteryComponent;
+use crate::estimator::{estimate_remaining, Estimate};
+
+/// How long samples are kept unless a history is created with its own window.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BatteryBackend {
+    Uwp,
+    Rfcomm,
+    Ble,
+    PowerShell,
+    Bluez,
+    Mock,
+}
+
+impl BatteryBackend {
+    pub const ALL: [BatteryBackend; 6] = [
+        BatteryBackend::Uwp,
+        BatteryBackend::Rfcomm,
+        BatteryBackend::Ble,
+        BatteryBackend::PowerShell,
+        BatteryBackend::Bluez,
+        BatteryBackend::Mock,
+    ];
+}
+
+impl FromStr for BatteryBackend {
+    type Err = anyhow::Error;
+
+    /// Accepts the variant name in any case, e.g. `rfcomm`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|backend| s.trim().eq_ignore_ascii_case(&format!("{:?}", backend)))
+            .ok_or_else(|| anyhow!("Unknown backend '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    Connected,
+    Disconnected,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatterySample {
+    /// Wall-clock time in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub level: u8,
+    pub source: BatteryBackend,
+    pub connection: ConnectionState,
+    /// Samples stored before components were tracked are `Overall`.
+    #[serde(default)]
+    pub component: BatteryComponent,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatteryHistory {
+    /// Ordered by timestamp, oldest first.
+    samples: Vec<BatterySample>,
+    retention: Duration,
+}
+
+impl BatteryHistory {
+    pub fn new() -> Self {
+        Self::with_retention(DEFAULT_RETENTION)
+    }
+
+    pub fn with_retention(retention: Duration) -> Self {
+        Self {
+            samples: Vec::new(),
+            retention,
+        }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    pub fn set_retention(&mut self, retention: Duration) {
+        self.retention = retention;
+        self.prune();
+    }
+
+    /// Adds a sample, keeping the history ordered and within the retention window.
+    pub fn record(&mut self, sample: BatterySample) {
+        let index = self.samples.partition_point(|s| s.timestamp <= sample.timestamp);
+        self.samples.insert(index, sample);
+        self.prune();
+    }
+
+    /// Adds the samples of `other` that this history does not have yet, e.g.
+    /// those another process wrote to the shared store. Both are ordered, so
+    /// this is a single pass; duplicates can only share a timestamp.
+    pub fn merge(&mut self, other: &BatteryHistory) {
+        let ours = std::mem::take(&mut self.samples);
+        let theirs = other.samples();
+        let mut merged = Vec::with_capacity(ours.len().max(theirs.len()));
+        let (mut i, mut j) = (0, 0);
+
+        while let Some(timestamp) = ours.get(i).into_iter().chain(theirs.get(j)).map(|s| s.timestamp).min() {
+            let run = merged.len();
+            while let Some(sample) = ours.get(i).filter(|s| s.timestamp == timestamp) {
+                merged.push(*sample);
+                i += 1;
+            }
+            while let Some(sample) = theirs.get(j).filter(|s| s.timestamp == timestamp) {
+                if !merged[run..].contains(sample) {
+                    merged.push(*sample);
+                }
+                j += 1;
+            }
+        }
+
+        self.samples = merged;
+        self.prune();
+    }
+
+    pub fn samples(&self) -> &[BatterySample] {
+        &self.samples
+    }
+
+    pub fn latest(&self) -> Option<&BatterySample> {
+        self.samples.last()
+    }
+
+    /// Samples with `start <= timestamp < end`.
+    pub fn samples_between(&self, start: u64, end: u64) -> &[BatterySample] {
+        let from = self.samples.partition_point(|s| s.timestamp < start);
+        let to = self.samples.partition_point(|s| s.timestamp < end).max(from);
+        &self.samples[from..to]
+    }
+
+    /// Remaining-time estimate of the overall level.
+    pub fn estimate(&self, current_level: u8) -> Estimate {
+        self.estimate_component(BatteryComponent::Overall, current_level)
+    }
+
+    /// Remaining-time estimate for `current_level` of one component, fitted
+    /// on that component's samples only. Samples taken while the device was
+    /// disconnected are usually cached values and are ignored.
+    pub fn estimate_component(&self, component: BatteryComponent, current_level: u8) -> Estimate {
+        let connected: Vec<BatterySample> = self
+            .samples
+            .iter()
+            .filter(|s| s.component == component && s.connection != ConnectionState::Disconnected)
+            .copied()
+            .collect();
+        estimate_remaining(&connected, current_level)
+    }
+
+    fn prune(&mut self) {
+        let Some(newest) = self.samples.last() else {
+            return;
+        };
+        let cutoff = newest.timestamp.saturating_sub(self.retention.as_secs());
+        let expired = self.samples.partition_point(|s| s.timestamp < cutoff);
+        self.samples.drain(..expired);
+    }
+}
+
+impl Default for BatteryHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, level: u8) -> BatterySample {
+        BatterySample {
+            timestamp,
+            level,
+            source: BatteryBackend::Uwp,
+            connection: ConnectionState::Connected,
+            component: BatteryComponent::Overall,
+        }
+    }
+
+    #[test]
+    fn test_record_keeps_samples_ordered() {
+        let mut history = BatteryHistory::new();
+        history.record(sample(300, 70));
+        history.record(sample(100, 90));
+        history.record(sample(200, 80));
+
+        let timestamps: Vec<u64> = history.samples().iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 200, 300]);
+        assert_eq!(history.latest().map(|s| s.level), Some(70));
+    }
+
+    #[test]
+    fn test_retention_drops_old_samples() {
+        let mut history = BatteryHistory::with_retention(Duration::from_secs(3600));
+        history.record(sample(0, 100));
+        history.record(sample(1800, 95));
+        history.record(sample(5000, 90));
+
+        let levels: Vec<u8> = history.samples().iter().map(|s| s.level).collect();
+        assert_eq!(levels, vec![95, 90]);
+
+        history.set_retention(Duration::from_secs(60));
+        assert_eq!(history.samples().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_skips_known_samples() {
+        let mut ours = BatteryHistory::new();
+        ours.record(sample(100, 90));
+        ours.record(sample(300, 70));
+        let mut theirs = BatteryHistory::new();
+        theirs.record(sample(100, 90));
+        theirs.record(sample(200, 80));
+
+        ours.merge(&theirs);
+        let timestamps: Vec<u64> = ours.samples().iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 200, 300]);
+
+        // Different samples taken in the same second are all kept, once
+        theirs.record(sample(300, 71));
+        theirs.record(sample(300, 70));
+        ours.merge(&theirs);
+        let levels: Vec<u8> = ours.samples().iter().map(|s| s.level).collect();
+        assert_eq!(levels, vec![90, 80, 70, 71]);
+    }
+
+    #[test]
+    fn test_samples_between() {
+        let mut history = BatteryHistory::new();
+        for t in 0..10 {
+            history.record(sample(t * 100, 100 - t as u8));
+        }
+
+        let range = history.samples_between(200, 500);
+        let timestamps: Vec<u64> = range.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![200, 300, 400]);
+
+        assert!(history.samples_between(2000, 3000).is_empty());
+        assert!(history.samples_between(500, 200).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_ignores_disconnected_samples() {
+        let mut history = BatteryHistory::new();
+        history.record(sample(0, 100));
+        for i in 1..=4 {
+            history.record(BatterySample {
+                connection: ConnectionState::Disconnected,
+                ..sample(i * 900, 100 - i as u8 * 10)
+            });
+        }
+
+        assert_eq!(history.estimate(60).accuracy.label(), "Measuring");
+    }
+
+    #[test]
+    fn test_components_are_estimated_separately() {
+        let mut history = BatteryHistory::new();
+        for i in 0..=4 {
+            // Left drains 20%/h, right 10%/h
+            history.record(BatterySample {
+                component: BatteryComponent::Left,
+                ..sample(i * 900, 100 - i as u8 * 5)
+            });
+            history.record(BatterySample {
+                component: BatteryComponent::Right,
+                ..sample(i * 900, 100 - i as u8 * 2)
+            });
+        }
+
+        assert_eq!(history.estimate_component(BatteryComponent::Left, 80).format_remaining(), "4h 0m");
+        assert_eq!(history.estimate_component(BatteryComponent::Right, 80).accuracy.label(), "Estimated");
+        assert_eq!(history.estimate(80).accuracy.label(), "Measuring");
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use bt_battery_estimator::config::RELOAD_INTERVAL;
+use bt_battery_estimator::report::{self, OutputFormat};
+use bt_battery_estimator::{
+    BatteryMonitor, BluetoothAddress, Config, ConfigWatcher, DeviceFilter, DeviceRegistry, Poller,
+};
+
+const USAGE: &str = "\
+Usage: bt-battery [--config FILE] [--format table|json|csv] [--threshold PERCENT] [FILTER...]
+       bt-battery daemon [--config FILE] [--format table|json|csv] [--min-interval SECS] [--max-interval SECS] [FILTER...]
+       bt-battery set ADDRESS KEY=VALUE...
+
+Refreshes all Bluetooth devices once and prints their battery levels.
+
+Settings are read from config.toml in the configuration directory, or
+from --config FILE. Options given here override the file.
+
+Filters (--allow and --deny can be repeated):
+  FILTER                same as --allow FILTER
+  --allow RULE          only show devices matching one of the rules
+  --deny RULE           hide devices matching the rule
+  --only-with-battery   hide devices that report no battery level
+A RULE is a device address, type:TYPE (e.g. type:gamepad), or part of
+the device name.
+
+The daemon keeps polling in the background, each device on its own
+interval, and records every reading in the shared history until stopped
+with Ctrl+C. It prints the device list after each poll, and applies
+edits to the configuration file without restarting. --min-interval and
+--max-interval only apply to the daemon.
+
+Exit status:
+  0  all devices at or above the threshold (or no threshold given)
+  1  invalid arguments or another error
+  2  at least one device (or earbud/case) is below the threshold, or
+     below its own low-battery threshold in the device registry
+
+Aliases, device types, rated runtimes, low-battery thresholds, hidden
+devices, preferred backends and icons are read from devices.json in the
+data directory. `set` changes the entry of one device there; the keys are
+alias, device_type, rated_runtime_hours, low_battery_threshold, hidden,
+preferred_backend and icon, and an empty VALUE clears a key. Running
+instances pick up the change when restarted.";
+
+/// Some device reported a level below `--threshold` or its own threshold.
+const EXIT_BELOW_THRESHOLD: u8 = 2;
+
+#[derive(PartialEq, Eq)]
+enum Command {
+    Once,
+    Daemon,
+    /// Edits the device registry instead of reading any battery.
+    Set {
+        address: BluetoothAddress,
+        edits: Vec<(String, String)>,
+    },
+}
+
+struct Args {
+    command: Command,
+    format: OutputFormat,
+    threshold: Option<u8>,
+    config: Option<PathBuf>,
+    min_interval: Option<Duration>,
+    max_interval: Option<Duration>,
+    /// Replaces the configured filter when any filter option is given.
+    filter: Option<DeviceFilter>,
+}
+
+impl Args {
+    /// The configuration with the command line options applied.
+    fn apply_to(&self, mut config: Config) -> Result<Config> {
+        if let Some(interval) = self.min_interval {
+            config.polling.min_interval_secs = interval.as_secs();
+        }
+        if let Some(interval) = self.max_interval {
+            config.polling.max_interval_secs = interval.as_secs();
+        }
+        if let Some(filter) = &self.filter {
+            config.filter = filter.clone();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>> {
+    let mut parsed = Args {
+        command: Command::Once,
+        format: OutputFormat::Table,
+        threshold: None,
+        config: None,
+        min_interval: None,
+        max_interval: None,
+        filter: None,
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "daemon" && parsed.command == Command::Once {
+            parsed.command = Command::Daemon;
+            continue;
+        }
+        if arg == "set" && parsed.command == Command::Once {
+            parsed.command = parse_set(&mut args)?;
+            continue;
+        }
+        if !arg.starts_with('-') {
+            parsed.filter.get_or_insert_with(DeviceFilter::default).allow.push(arg.parse()?);
+            continue;
+        }
+
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| anyhow!("{} needs a value", flag))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-f" | "--format" => parsed.format = value()?.parse()?,
+            "-t" | "--threshold" => {
+                let raw = value()?;
+                let threshold: u8 = raw
+                    .parse()
+                    .ok()
+                    .filter(|t| *t <= 100)
+                    .with_context(|| format!("Invalid threshold '{}' (expected 0-100)", raw))?;
+                parsed.threshold = Some(threshold);
+            }
+            "-c" | "--config" => parsed.config = Some(PathBuf::from(value()?)),
+            "--min-interval" => parsed.min_interval = Some(seconds(&flag, &value()?)?),
+            "--max-interval" => parsed.max_interval = Some(seconds(&flag, &value()?)?),
+            "--allow" => parsed.filter.get_or_insert_with(DeviceFilter::default).allow.push(value()?.parse()?),
+            "--deny" => parsed.filter.get_or_insert_with(DeviceFilter::default).deny.push(value()?.parse()?),
+            "--only-with-battery" => parsed.filter.get_or_insert_with(DeviceFilter::default).only_with_battery = true,
+            other => return Err(anyhow!("Unknown argument '{}'", other)),
+        }
+    }
+
+    if parsed.command == Command::Once && (parsed.min_interval.is_some() || parsed.max_interval.is_some()) {
+        return Err(anyhow!("--min-interval and --max-interval only apply to the daemon"));
+    }
+    if let (Some(min), Some(max)) = (parsed.min_interval, parsed.max_interval) {
+        if min > max {
+            return Err(anyhow!("--min-interval must not be larger than --max-interval"));
+        }
+    }
+    Ok(Some(parsed))
+}
+
+/// `set ADDRESS KEY=VALUE...`, taking the rest of the arguments.
+fn parse_set(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let address = args
+        .next()
+        .ok_or_else(|| anyhow!("set needs a device address"))?
+        .parse()?;
+    let edits = args
+        .map(|arg| match arg.split_once('=') {
+            Some((key, value)) => Ok((key.to_string(), value.to_string())),
+            None => Err(anyhow!("Expected KEY=VALUE, not '{}'", arg)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if edits.is_empty() {
+        return Err(anyhow!("set needs at least one KEY=VALUE"));
+    }
+    Ok(Command::Set { address, edits })
+}
+
+/// Applies `edits` to the device's entry in the registry and prints the
+/// resulting settings.
+fn set_device(address: BluetoothAddress, edits: &[(String, String)]) -> Result<()> {
+    let path = DeviceRegistry::default_path().context("No data directory for the device registry")?;
+    let mut registry = DeviceRegistry::load(&path)?;
+    let mut settings = registry.get(&address.to_string());
+    for (key, value) in edits {
+        settings.set_field(key, value)?;
+    }
+    registry
+        .set(address, settings.clone())
+        .with_context(|| format!("Invalid settings for {}", address))?;
+    registry.save(&path)?;
+    println!("{}", serde_json::to_string_pretty(&settings)?);
+    Ok(())
+}
+
+/// The file named by `--config` must exist; the default one is optional.
+fn load_config(args: &Args) -> Result<Config> {
+    if let Some(path) = args.config.as_ref().filter(|path| !path.exists()) {
+        return Err(anyhow!("{} does not exist", path.display()));
+    }
+    let config = match args.config.clone().or_else(Config::default_path) {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    args.apply_to(config)
+}
+
+fn seconds(flag: &str, raw: &str) -> Result<Duration> {
+    raw.parse()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .with_context(|| format!("Invalid value '{}' for {} (expected seconds)", raw, flag))
+}
+
+async fn run_daemon(monitor: BatteryMonitor, config: &Config, args: Args) -> ExitCode {
+    let monitor = Arc::new(monitor);
+    let format = args.format;
+    let (poll_updates, poll_config) = watch::channel(config.poll_config());
+    let poller = Poller::new(monitor.clone(), config.poll_config())
+        .with_config_updates(poll_config)
+        .on_poll(move |devices| match report::render(devices, format) {
+            Ok(output) => print!("{}", output),
+            Err(e) => eprintln!("Failed to format output: {:#}", e),
+        });
+
+    if let Some(path) = args.config.clone().or_else(Config::default_path) {
+        let watcher = ConfigWatcher::new(path);
+        eprintln!("Watching {} for changes", watcher.path().display());
+        let monitor = monitor.clone();
+        tokio::spawn(watcher.watch(RELOAD_INTERVAL, move |config| match args.apply_to(config) {
+            Ok(config) => {
+                monitor.apply_config(&config);
+                let _ = poll_updates.send(config.poll_config());
+                eprintln!("Configuration reloaded");
+            }
+            Err(e) => eprintln!("Keeping the previous configuration: {:#}", e),
+        }));
+    }
+
+    tokio::select! {
+        _ = poller.run() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    monitor.flush_history().await;
+    ExitCode::SUCCESS
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{:#}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Command::Set { address, edits } = &args.command {
+        return match set_device(*address, edits) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{:#}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let monitor = BatteryMonitor::configured();
+    monitor.apply_config(&config);
+    if args.command == Command::Daemon {
+        return run_daemon(monitor, &config, args).await;
+    }
+
+    let devices = monitor.refresh().await;
+    monitor.flush_history().await;
+
+    match report::render(&devices, args.format) {
+        Ok(output) => print!("{}", output),
+        Err(e) => {
+            eprintln!("Failed to format output: {:#}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Low-battery thresholds set in the device registry always count
+    if !report::below_threshold(&devices, args.threshold.unwrap_or(0)).is_empty() {
+        return ExitCode::from(EXIT_BELOW_THRESHOLD);
+    }
+    ExitCode::SUCCESS
+}
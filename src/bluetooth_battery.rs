@@ -1,6 +1,28 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The part of a device a level belongs to. Single-battery devices only
+/// report `Overall`; true-wireless earbuds report `Left`, `Right` and `Case`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BatteryComponent {
+    #[default]
+    Overall,
+    Left,
+    Right,
+    Case,
+}
+
+impl BatteryComponent {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BatteryComponent::Overall => "Overall",
+            BatteryComponent::Left => "Left",
+            BatteryComponent::Right => "Right",
+            BatteryComponent::Case => "Case",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BatteryResult {
     pub overall: Option<u8>,
     pub left: Option<u8>,
@@ -18,6 +40,18 @@ impl BatteryResult {
         }
     }
 
+    /// The levels that were reported, tagged with their component.
+    pub fn components(&self) -> impl Iterator<Item = (BatteryComponent, u8)> {
+        [
+            (BatteryComponent::Overall, self.overall),
+            (BatteryComponent::Left, self.left),
+            (BatteryComponent::Right, self.right),
+            (BatteryComponent::Case, self.case),
+        ]
+        .into_iter()
+        .filter_map(|(component, level)| level.map(|level| (component, level)))
+    }
+
     pub fn get_primary_level(&self) -> Option<u8> {
         if let Some(overall) = self.overall {
             return Some(overall);
@@ -33,6 +67,111 @@ impl BatteryResult {
     }
 }
 
+/// `Namespace` value of the Characteristic Presentation Format descriptor
+/// for descriptions assigned by the Bluetooth SIG.
+pub const BLUETOOTH_SIG_NAMESPACE: u8 = 0x01;
+
+/// The parts of a Characteristic Presentation Format descriptor (0x2904)
+/// that tell Battery Service instances apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationFormat {
+    pub namespace: u8,
+    pub description: u16,
+}
+
+impl PresentationFormat {
+    /// Parses the 7-byte descriptor value: format, exponent, unit (u16),
+    /// namespace, description (u16), all little-endian.
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        if value.len() < 7 {
+            return None;
+        }
+        Some(Self {
+            namespace: value[4],
+            description: u16::from_le_bytes([value[5], value[6]]),
+        })
+    }
+
+    /// The component a SIG namespace description stands for.
+    pub fn component(&self) -> Option<BatteryComponent> {
+        if self.namespace != BLUETOOTH_SIG_NAMESPACE {
+            return None;
+        }
+        match self.description {
+            0x0106 => Some(BatteryComponent::Overall), // "main"
+            0x010D => Some(BatteryComponent::Left),
+            0x010E => Some(BatteryComponent::Right),
+            0x010C | 0x0110 => Some(BatteryComponent::Case), // "outside", "external"
+            _ => None,
+        }
+    }
+}
+
+/// One Battery Service (0x180F) instance of a device, in attribute handle order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryServiceInstance {
+    /// Battery Level (0x2A19) value.
+    pub level: u8,
+    pub format: Option<PresentationFormat>,
+}
+
+impl BatteryResult {
+    /// Maps Battery Service instances to components. Instances are placed by
+    /// their presentation format where it names a component; the rest fill
+    /// the free slots in order: a lone instance is the overall level,
+    /// otherwise left, right, then case.
+    pub fn from_service_instances(instances: &[BatteryServiceInstance]) -> Self {
+        let mut result = BatteryResult::new();
+        let mut unplaced = Vec::new();
+
+        for instance in instances {
+            match instance.format.and_then(|f| f.component()) {
+                Some(component) if result.level_of(component).is_none() => {
+                    result.set_level(component, instance.level)
+                }
+                _ => unplaced.push(instance.level),
+            }
+        }
+
+        if instances.len() == 1 && unplaced.len() == 1 {
+            result.overall = Some(unplaced[0]);
+            return result;
+        }
+
+        let mut free = [BatteryComponent::Left, BatteryComponent::Right, BatteryComponent::Case]
+            .into_iter()
+            .filter(|component| result.level_of(*component).is_none())
+            .collect::<Vec<_>>()
+            .into_iter();
+        for level in unplaced {
+            match free.next() {
+                Some(component) => result.set_level(component, level),
+                None => break,
+            }
+        }
+        result
+    }
+
+    pub fn level_of(&self, component: BatteryComponent) -> Option<u8> {
+        match component {
+            BatteryComponent::Overall => self.overall,
+            BatteryComponent::Left => self.left,
+            BatteryComponent::Right => self.right,
+            BatteryComponent::Case => self.case,
+        }
+    }
+
+    pub fn set_level(&mut self, component: BatteryComponent, level: u8) {
+        let slot = match component {
+            BatteryComponent::Overall => &mut self.overall,
+            BatteryComponent::Left => &mut self.left,
+            BatteryComponent::Right => &mut self.right,
+            BatteryComponent::Case => &mut self.case,
+        };
+        *slot = Some(level);
+    }
+}
+
 pub struct BluetoothBatteryQuerier {
     device_mac: String,
 }
@@ -42,6 +181,10 @@ impl BluetoothBatteryQuerier {
         Self { device_mac }
     }
 
+    pub fn device_mac(&self) -> &str {
+        &self.device_mac
+    }
+
     pub async fn query_battery(&self) -> Result<BatteryResult, Box<dyn std::error::Error>> {
         // Try different methods to get battery information
         
@@ -81,10 +224,31 @@ impl BluetoothBatteryQuerier {
     }
 
     async fn query_ble_battery(&self) -> Result<BatteryResult, Box<dyn std::error::Error>> {
-        // TODO: Implement BLE GATT battery service query
-        // This would use btleplug to connect to BLE devices and read
-        // the standard Battery Service (0x180F)
-        
+        Ok(self.read_gatt_battery().await?)
+    }
+
+    /// Reads every Battery Service instance of the device over GATT and maps
+    /// them to components.
+    ///
+    /// This goes through WinRT rather than btleplug: btleplug addresses
+    /// characteristics by UUID alone, so it only ever sees one of several
+    /// Battery Level characteristics.
+    #[cfg(windows)]
+    pub async fn read_gatt_battery(&self) -> anyhow::Result<BatteryResult> {
+        use windows::Devices::Bluetooth::BluetoothLEDevice;
+
+        let address = crate::at_commands::parse_mac_address(&self.device_mac)?;
+        tokio::task::spawn_blocking(move || {
+            let device = BluetoothLEDevice::FromBluetoothAddressAsync(address)?.get()?;
+            let instances = crate::uwp_bluetooth::read_battery_service_instances(&device)?;
+            Ok::<_, anyhow::Error>(BatteryResult::from_service_instances(&instances))
+        })
+        .await?
+    }
+
+    #[cfg(not(windows))]
+    pub async fn read_gatt_battery(&self) -> anyhow::Result<BatteryResult> {
+        // BlueZ exposes the Battery Service itself; see sources::bluez
         Ok(BatteryResult::new())
     }
 
@@ -124,6 +288,56 @@ mod tests {
         assert_eq!(result.get_primary_level(), Some(50));
     }
 
+    fn instance(level: u8, description: Option<u16>) -> BatteryServiceInstance {
+        BatteryServiceInstance {
+            level,
+            format: description.map(|description| PresentationFormat {
+                namespace: BLUETOOTH_SIG_NAMESPACE,
+                description,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_presentation_format_parse() {
+        // uint8, exponent 0, unit percentage (0x27AD), SIG namespace, "left"
+        let format = PresentationFormat::parse(&[0x04, 0x00, 0xAD, 0x27, 0x01, 0x0D, 0x01]).unwrap();
+        assert_eq!(format.description, 0x010D);
+        assert_eq!(format.component(), Some(BatteryComponent::Left));
+        assert!(PresentationFormat::parse(&[0x04, 0x00]).is_none());
+
+        let vendor = PresentationFormat { namespace: 0x02, description: 0x010D };
+        assert_eq!(vendor.component(), None);
+    }
+
+    #[test]
+    fn test_service_instances_map_by_description() {
+        let result = BatteryResult::from_service_instances(&[
+            instance(40, Some(0x0110)),
+            instance(70, Some(0x010E)),
+            instance(80, Some(0x010D)),
+        ]);
+        assert_eq!((result.left, result.right, result.case), (Some(80), Some(70), Some(40)));
+        assert_eq!(result.overall, None);
+
+        let main = BatteryResult::from_service_instances(&[instance(55, Some(0x0106))]);
+        assert_eq!(main.overall, Some(55));
+    }
+
+    #[test]
+    fn test_service_instances_fall_back_to_order() {
+        let single = BatteryResult::from_service_instances(&[instance(90, None)]);
+        assert_eq!(single.overall, Some(90));
+
+        // The described right earbud keeps its slot; the others fill in order
+        let result = BatteryResult::from_service_instances(&[
+            instance(60, None),
+            instance(70, Some(0x010E)),
+            instance(100, None),
+        ]);
+        assert_eq!((result.left, result.right, result.case), (Some(60), Some(70), Some(100)));
+    }
+
     #[tokio::test]
     async fn test_battery_querier() {
         let querier = BluetoothBatteryQuerier::new("00:11:22:33:44:55".to_string());
@@ -0,0 +1,378 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::battery_history::BatteryBackend;
+use crate::device_type::DeviceType;
+use crate::filter::DeviceFilter;
+use crate::history_store::APP_DIR_NAME;
+use crate::poller::PollConfig;
+use crate::rfcomm_io::RfcommTimeouts;
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// How often `ConfigWatcher::watch` looks at the file.
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Contents of `config.toml`. Every key is optional; missing ones keep the
+/// defaults below.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub polling: PollingOptions,
+    pub sources: SourceOptions,
+    pub rfcomm: RfcommOptions,
+    pub filter: DeviceFilter,
+    pub estimate: EstimateOptions,
+    pub ui: UiOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollingOptions {
+    pub min_interval_secs: u64,
+    pub max_interval_secs: u64,
+    /// At or below this level a device is polled at the minimum interval.
+    pub low_level: u8,
+    pub discovery_interval_secs: u64,
+}
+
+impl Default for PollingOptions {
+    fn default() -> Self {
+        let defaults = PollConfig::default();
+        Self {
+            min_interval_secs: defaults.min_interval.as_secs(),
+            max_interval_secs: defaults.max_interval.as_secs(),
+            low_level: defaults.low_level,
+            discovery_interval_secs: defaults.discovery_interval.as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceOptions {
+    /// Backends asked first, e.g. `["Rfcomm", "Ble"]`. The others follow in
+    /// their default order.
+    pub priority: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RfcommOptions {
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+    pub write_timeout_secs: u64,
+    pub session_timeout_secs: u64,
+}
+
+impl Default for RfcommOptions {
+    fn default() -> Self {
+        let defaults = RfcommTimeouts::default();
+        Self {
+            connect_timeout_secs: defaults.connect.as_secs(),
+            read_timeout_secs: defaults.read.as_secs(),
+            write_timeout_secs: defaults.write.as_secs(),
+            session_timeout_secs: defaults.session.as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EstimateOptions {
+    /// Runtime on a full charge by device type (e.g. `Earbuds = 6`), used
+    /// instead of the built-in table until there is a measured rate. A
+    /// rated runtime in the device registry takes precedence.
+    pub rated_runtime_hours: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiOptions {
+    /// Show the device type after the name.
+    pub show_device_type: bool,
+    /// List earbuds and case separately.
+    pub show_components: bool,
+    /// Levels below this are highlighted, unless the device registry sets
+    /// a threshold of its own.
+    pub low_battery_threshold: u8,
+}
+
+impl Default for UiOptions {
+    fn default() -> Self {
+        Self {
+            show_device_type: true,
+            show_components: true,
+            low_battery_threshold: PollConfig::default().low_level,
+        }
+    }
+}
+
+impl Config {
+    /// `config.toml` in the per-user configuration directory, if one can be
+    /// determined.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        };
+        base.map(|dir| dir.join(APP_DIR_NAME).join(CONFIG_FILE_NAME))
+    }
+
+    /// A missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents).with_context(|| format!("Invalid configuration {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let config: Config = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the types alone do not, naming the offending key.
+    pub fn validate(&self) -> Result<()> {
+        let polling = &self.polling;
+        positive("polling.min_interval_secs", polling.min_interval_secs)?;
+        positive("polling.max_interval_secs", polling.max_interval_secs)?;
+        positive("polling.discovery_interval_secs", polling.discovery_interval_secs)?;
+        if polling.min_interval_secs > polling.max_interval_secs {
+            return Err(anyhow!(
+                "polling.min_interval_secs ({}) must not be larger than polling.max_interval_secs ({})",
+                polling.min_interval_secs,
+                polling.max_interval_secs
+            ));
+        }
+        percentage("polling.low_level", polling.low_level)?;
+
+        let mut backends = Vec::new();
+        for name in &self.sources.priority {
+            let backend: BatteryBackend = name
+                .parse()
+                .with_context(|| format!("sources.priority lists '{}', which is not a backend", name))?;
+            if backends.contains(&backend) {
+                return Err(anyhow!("sources.priority lists {:?} twice", backend));
+            }
+            backends.push(backend);
+        }
+
+        positive("rfcomm.connect_timeout_secs", self.rfcomm.connect_timeout_secs)?;
+        positive("rfcomm.read_timeout_secs", self.rfcomm.read_timeout_secs)?;
+        positive("rfcomm.write_timeout_secs", self.rfcomm.write_timeout_secs)?;
+        positive("rfcomm.session_timeout_secs", self.rfcomm.session_timeout_secs)?;
+
+        for (device_type, hours) in &self.estimate.rated_runtime_hours {
+            let key = format!("estimate.rated_runtime_hours.{}", device_type);
+            device_type
+                .parse::<DeviceType>()
+                .with_context(|| format!("{} is not a device type", key))?;
+            if !(*hours > 0.0 && Duration::try_from_secs_f64(hours * 3600.0).is_ok()) {
+                return Err(anyhow!("{} must be a positive number of hours, not {:?}", key, hours));
+            }
+        }
+
+        percentage("ui.low_battery_threshold", self.ui.low_battery_threshold)
+    }
+
+    pub fn poll_config(&self) -> PollConfig {
+        PollConfig {
+            min_interval: Duration::from_secs(self.polling.min_interval_secs),
+            max_interval: Duration::from_secs(self.polling.max_interval_secs),
+            low_level: self.polling.low_level,
+            discovery_interval: Duration::from_secs(self.polling.discovery_interval_secs),
+        }
+    }
+
+    pub fn rfcomm_timeouts(&self) -> RfcommTimeouts {
+        RfcommTimeouts {
+            connect: Duration::from_secs(self.rfcomm.connect_timeout_secs),
+            read: Duration::from_secs(self.rfcomm.read_timeout_secs),
+            write: Duration::from_secs(self.rfcomm.write_timeout_secs),
+            session: Duration::from_secs(self.rfcomm.session_timeout_secs),
+        }
+    }
+
+    /// Backend order for `SourceChain::set_priority`. Only valid after
+    /// `validate`.
+    pub fn source_priority(&self) -> Vec<BatteryBackend> {
+        self.sources
+            .priority
+            .iter()
+            .filter_map(|name| name.parse().ok())
+            .collect()
+    }
+
+    /// The estimate table by device type. Entries `validate` rejects are
+    /// left out.
+    pub fn rated_runtimes(&self) -> HashMap<DeviceType, Duration> {
+        self.estimate
+            .rated_runtime_hours
+            .iter()
+            .filter_map(|(device_type, hours)| {
+                Some((device_type.parse().ok()?, Duration::try_from_secs_f64(hours * 3600.0).ok()?))
+            })
+            .collect()
+    }
+}
+
+fn positive(key: &str, value: u64) -> Result<()> {
+    if value == 0 {
+        return Err(anyhow!("{} must be greater than 0", key));
+    }
+    Ok(())
+}
+
+fn percentage(key: &str, value: u8) -> Result<()> {
+    if value > 100 {
+        return Err(anyhow!("{} must be 0-100, not {}", key, value));
+    }
+    Ok(())
+}
+
+/// Notices edits to the configuration file by polling its modification
+/// time and size.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+}
+
+impl ConfigWatcher {
+    /// Only edits made after this count as changes.
+    pub fn new(path: PathBuf) -> Self {
+        let stamp = file_stamp(&path);
+        Self { path, stamp }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reloads the file if it changed since the last call. A deleted file
+    /// goes back to the defaults.
+    pub fn poll(&mut self) -> Option<Result<Config>> {
+        let stamp = file_stamp(&self.path);
+        if stamp == self.stamp {
+            return None;
+        }
+        self.stamp = stamp;
+        Some(Config::load(&self.path))
+    }
+
+    /// Calls `on_change` with every valid new configuration. An invalid one
+    /// is reported and the previous one stays in effect.
+    pub async fn watch(mut self, interval: Duration, mut on_change: impl FnMut(Config) + Send) {
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.poll() {
+                Some(Ok(config)) => on_change(config),
+                Some(Err(e)) => eprintln!("Keeping the previous configuration: {:#}", e),
+                None => {}
+            }
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides_defaults() {
+        let config = Config::parse(
+            r#"
+            [polling]
+            max_interval_secs = 600
+
+            [sources]
+            priority = ["ble", "Rfcomm"]
+
+            [rfcomm]
+            read_timeout_secs = 8
+
+            [filter]
+            deny = ["type:phone"]
+
+            [estimate.rated_runtime_hours]
+            "hearing aid" = 30
+            Mouse = 720.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.poll_config().max_interval, Duration::from_secs(600));
+        assert_eq!(config.poll_config().min_interval, PollConfig::default().min_interval);
+        assert_eq!(config.source_priority(), [BatteryBackend::Ble, BatteryBackend::Rfcomm]);
+        assert_eq!(config.rfcomm_timeouts().read, Duration::from_secs(8));
+        assert_eq!(config.rfcomm_timeouts().connect, RfcommTimeouts::default().connect);
+        assert_eq!(config.filter.deny.len(), 1);
+        assert_eq!(config.rated_runtimes()[&DeviceType::HearingAid], Duration::from_secs(30 * 3600));
+        assert!(config.ui.show_components);
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse(include_str!("../config.example.toml")).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        for (contents, key) in [
+            ("[polling]\nmin_interval_secs = 0", "polling.min_interval_secs"),
+            ("[polling]\nmin_interval_secs = 120\nmax_interval_secs = 60", "polling.max_interval_secs"),
+            ("[sources]\npriority = [\"Carrier pigeon\"]", "sources.priority"),
+            ("[sources]\npriority = [\"Ble\", \"ble\"]", "sources.priority"),
+            ("[rfcomm]\nread_timeout_secs = \"5s\"", "read_timeout_secs"),
+            ("[rfcomm]\nread_timeout = 5", "read_timeout"),
+            ("[estimate.rated_runtime_hours]\nToaster = 3", "estimate.rated_runtime_hours.Toaster"),
+            ("[estimate.rated_runtime_hours]\nMouse = 1e300", "estimate.rated_runtime_hours.Mouse"),
+            ("[estimate.rated_runtime_hours]\nMouse = nan", "estimate.rated_runtime_hours.Mouse"),
+            ("[ui]\nlow_battery_threshold = 120", "ui.low_battery_threshold"),
+        ] {
+            let error = format!("{:#}", Config::parse(contents).unwrap_err());
+            assert!(error.contains(key), "{:?} gave {}", contents, error);
+        }
+
+        let mut config = Config::default();
+        config.estimate.rated_runtime_hours.insert("Mouse".to_string(), 1e300);
+        assert!(config.rated_runtimes().is_empty());
+    }
+
+    #[test]
+    fn test_watcher_reloads_changed_file() {
+        let dir = std::env::temp_dir().join(format!("bt-battery-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, "[ui]\nshow_components = false\n").unwrap();
+
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(watcher.poll().is_none());
+
+        fs::write(&path, "[ui]\nshow_components = true\nshow_device_type = false\n").unwrap();
+        let config = watcher.poll().unwrap().unwrap();
+        assert!(!config.ui.show_device_type);
+        assert!(watcher.poll().is_none());
+
+        fs::write(&path, "[ui]\nshow_device_type = maybe\n").unwrap();
+        assert!(watcher.poll().unwrap().is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(watcher.poll().unwrap().unwrap(), Config::default());
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::address::BluetoothAddress;
+use crate::battery_history::BatteryBackend;
+use crate::device_type::DeviceType;
+use crate::history_store::{data_dir, write_atomically};
+
+/// Bump when the on-disk layout changes.
+pub const REGISTRY_VERSION: u32 = 1;
+
+const REGISTRY_FILE_NAME: &str = "devices.json";
+
+/// What the user has told us about one device. Unset fields keep what the
+/// backends report.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceSettings {
+    /// Shown instead of the name the device reports.
+    pub alias: Option<String>,
+    /// Overrides the type derived from Class of Device, Appearance or name.
+    pub device_type: Option<DeviceType>,
+    /// Runtime on a full charge, used until there is enough history for a
+    /// measured rate.
+    pub rated_runtime_hours: Option<f64>,
+    /// Below this level the device counts as low on battery.
+    pub low_battery_threshold: Option<u8>,
+    pub hidden: bool,
+    /// Backend asked first for the battery level.
+    pub preferred_backend: Option<BatteryBackend>,
+    /// Image shown next to the device in the window.
+    pub icon: Option<PathBuf>,
+}
+
+impl DeviceSettings {
+    /// `None` when unset, or too large to be a `Duration`.
+    pub fn rated_runtime(&self) -> Option<Duration> {
+        self.rated_runtime_hours
+            .and_then(|hours| Duration::try_from_secs_f64(hours * 3600.0).ok())
+    }
+
+    /// Sets the field named `key` in `devices.json` from its text form, e.g.
+    /// `device_type` to `headset`. An empty `value` clears the field.
+    pub fn set_field(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        let result = match key {
+            "alias" => optional(value, |v| Ok(v.to_string())).map(|v| self.alias = v),
+            "device_type" => optional(value, str::parse).map(|v| self.device_type = v),
+            "rated_runtime_hours" => optional(value, |v| Ok(v.parse()?)).map(|v| self.rated_runtime_hours = v),
+            "low_battery_threshold" => optional(value, |v| Ok(v.parse()?)).map(|v| self.low_battery_threshold = v),
+            "hidden" => optional(value, |v| Ok(v.parse()?)).map(|v| self.hidden = v.unwrap_or_default()),
+            "preferred_backend" => optional(value, str::parse).map(|v| self.preferred_backend = v),
+            "icon" => optional(value, |v| Ok(PathBuf::from(v))).map(|v| self.icon = v),
+            other => {
+                return Err(anyhow!(
+                    "Unknown setting '{}' (expected alias, device_type, rated_runtime_hours, \
+                     low_battery_threshold, hidden, preferred_backend or icon)",
+                    other
+                ))
+            }
+        };
+        result.with_context(|| format!("Invalid value '{}' for {}", value, key))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(hours) = self.rated_runtime_hours {
+            if !(hours > 0.0 && Duration::try_from_secs_f64(hours * 3600.0).is_ok()) {
+                return Err(anyhow!("rated_runtime_hours must be a positive number of hours, not {:?}", hours));
+            }
+        }
+        if let Some(threshold) = self.low_battery_threshold {
+            if threshold > 100 {
+                return Err(anyhow!("low_battery_threshold must be 0-100, not {}", threshold));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `None` for an empty `value`, otherwise what `parse` makes of it.
+fn optional<T>(value: &str, parse: impl FnOnce(&str) -> Result<T>) -> Result<Option<T>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    parse(value).map(Some)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRegistry {
+    version: u32,
+    devices: BTreeMap<BluetoothAddress, DeviceSettings>,
+}
+
+/// Per-device settings keyed by address, kept in a JSON file next to the
+/// battery history.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceRegistry {
+    devices: BTreeMap<BluetoothAddress, DeviceSettings>,
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// File in the per-user data directory, if one can be determined.
+    pub fn default_path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join(REGISTRY_FILE_NAME))
+    }
+
+    /// A missing file is an empty registry.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        let stored: StoredRegistry =
+            serde_json::from_str(&contents).with_context(|| format!("Invalid device registry {}", path.display()))?;
+        if stored.version > REGISTRY_VERSION {
+            return Err(anyhow!(
+                "Device registry version {} is not supported (expected at most {})",
+                stored.version,
+                REGISTRY_VERSION
+            ));
+        }
+        for (address, settings) in &stored.devices {
+            settings
+                .validate()
+                .with_context(|| format!("Invalid settings for {} in {}", address, path.display()))?;
+        }
+
+        Ok(Self {
+            devices: stored.devices,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let stored = StoredRegistry {
+            version: REGISTRY_VERSION,
+            devices: self.devices.clone(),
+        };
+        write_atomically(path, &serde_json::to_vec_pretty(&stored)?)
+    }
+
+    /// Settings of the device at `mac_address`; defaults for unknown devices.
+    pub fn get(&self, mac_address: &str) -> DeviceSettings {
+        mac_address
+            .parse::<BluetoothAddress>()
+            .ok()
+            .and_then(|address| self.devices.get(&address))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, address: BluetoothAddress, settings: DeviceSettings) -> Result<()> {
+        settings.validate()?;
+        if settings == DeviceSettings::default() {
+            self.devices.remove(&address);
+        } else {
+            self.devices.insert(address, settings);
+        }
+        Ok(())
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = (&BluetoothAddress, &DeviceSettings)> {
+        self.devices.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bt-battery-registry-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(REGISTRY_FILE_NAME)
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = temp_path("round-trip");
+        assert_eq!(DeviceRegistry::load(&path).unwrap(), DeviceRegistry::new());
+
+        let address: BluetoothAddress = "00:11:22:33:44:55".parse().unwrap();
+        let settings = DeviceSettings {
+            alias: Some("Desk Jabra".to_string()),
+            device_type: Some(DeviceType::Headset),
+            rated_runtime_hours: Some(30.0),
+            low_battery_threshold: Some(15),
+            hidden: false,
+            preferred_backend: Some(BatteryBackend::Rfcomm),
+            icon: Some(PathBuf::from("icons/jabra.png")),
+        };
+        let mut registry = DeviceRegistry::new();
+        registry.set(address, settings.clone()).unwrap();
+        registry.save(&path).unwrap();
+
+        let loaded = DeviceRegistry::load(&path).unwrap();
+        assert_eq!(loaded.get("00-11-22-33-44-55"), settings);
+        assert_eq!(loaded.get("66:77:88:99:AA:BB"), DeviceSettings::default());
+        assert_eq!(settings.rated_runtime(), Some(Duration::from_secs(30 * 3600)));
+    }
+
+    #[test]
+    fn test_set_fields_from_text() {
+        let mut settings = DeviceSettings::default();
+        for (key, value) in [
+            ("alias", "Desk Jabra"),
+            ("device_type", "headset"),
+            ("rated_runtime_hours", "30"),
+            ("hidden", "true"),
+            ("preferred_backend", "rfcomm"),
+            ("icon", "icons/jabra.png"),
+        ] {
+            settings.set_field(key, value).unwrap();
+        }
+        assert_eq!(settings.alias.as_deref(), Some("Desk Jabra"));
+        assert_eq!(settings.device_type, Some(DeviceType::Headset));
+        assert_eq!(settings.rated_runtime_hours, Some(30.0));
+        assert!(settings.hidden);
+        assert_eq!(settings.preferred_backend, Some(BatteryBackend::Rfcomm));
+        assert_eq!(settings.icon, Some(PathBuf::from("icons/jabra.png")));
+
+        settings.set_field("alias", "").unwrap();
+        settings.set_field("hidden", "").unwrap();
+        assert_eq!(settings.alias, None);
+        assert!(!settings.hidden);
+
+        let error = format!("{:#}", settings.set_field("low_battery_threshold", "lots").unwrap_err());
+        assert!(error.contains("low_battery_threshold"), "{}", error);
+        assert!(settings.set_field("colour", "red").is_err());
+        assert!(settings.set_field("preferred_backend", "carrier pigeon").is_err());
+    }
+
+    #[test]
+    fn test_invalid_settings_name_the_device() {
+        let path = temp_path("invalid");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{ "version": 1, "devices": { "00:11:22:33:44:55": { "low_battery_threshold": 150 } } }"#,
+        )
+        .unwrap();
+
+        let error = format!("{:#}", DeviceRegistry::load(&path).unwrap_err());
+        assert!(error.contains("00:11:22:33:44:55"), "{}", error);
+        assert!(error.contains("low_battery_threshold"), "{}", error);
+
+        let too_long = DeviceSettings {
+            rated_runtime_hours: Some(1e300),
+            ..DeviceSettings::default()
+        };
+        let error = DeviceRegistry::new()
+            .set("00:11:22:33:44:55".parse().unwrap(), too_long.clone())
+            .unwrap_err();
+        assert!(error.to_string().contains("rated_runtime_hours"), "{}", error);
+        assert_eq!(too_long.rated_runtime(), None);
+    }
+}
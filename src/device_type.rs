@@ -0,0 +1,239 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What kind of device a Bluetooth device is, from the most specific source
+/// available: the BLE GAP Appearance, then the Class of Device, then the name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeviceType {
+    Mouse,
+    Keyboard,
+    Trackpad,
+    Gamepad,
+    Stylus,
+    RemoteControl,
+    Headset,
+    Headphones,
+    Earbuds,
+    Speaker,
+    HearingAid,
+    Watch,
+    Phone,
+    Computer,
+    #[default]
+    Other,
+}
+
+impl DeviceType {
+    pub const ALL: [DeviceType; 15] = [
+        DeviceType::Mouse,
+        DeviceType::Keyboard,
+        DeviceType::Trackpad,
+        DeviceType::Gamepad,
+        DeviceType::Stylus,
+        DeviceType::RemoteControl,
+        DeviceType::Headset,
+        DeviceType::Headphones,
+        DeviceType::Earbuds,
+        DeviceType::Speaker,
+        DeviceType::HearingAid,
+        DeviceType::Watch,
+        DeviceType::Phone,
+        DeviceType::Computer,
+        DeviceType::Other,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeviceType::Mouse => "Mouse",
+            DeviceType::Keyboard => "Keyboard",
+            DeviceType::Trackpad => "Trackpad",
+            DeviceType::Gamepad => "Gamepad",
+            DeviceType::Stylus => "Stylus",
+            DeviceType::RemoteControl => "Remote",
+            DeviceType::Headset => "Headset",
+            DeviceType::Headphones => "Headphones",
+            DeviceType::Earbuds => "Earbuds",
+            DeviceType::Speaker => "Speaker",
+            DeviceType::HearingAid => "Hearing aid",
+            DeviceType::Watch => "Watch",
+            DeviceType::Phone => "Phone",
+            DeviceType::Computer => "Computer",
+            DeviceType::Other => "Other",
+        }
+    }
+
+    /// Uses the first of `appearance`, `class_of_device` and `name` that
+    /// says something specific.
+    pub fn classify(class_of_device: Option<u32>, appearance: Option<u16>, name: &str) -> Self {
+        appearance
+            .and_then(Self::from_appearance)
+            .or_else(|| class_of_device.and_then(Self::from_class_of_device))
+            .or_else(|| Self::from_name(name))
+            .unwrap_or_default()
+    }
+
+    /// From the GAP Appearance characteristic (category in bits 15-6,
+    /// subcategory in bits 5-0). Generic values give `None`.
+    pub fn from_appearance(appearance: u16) -> Option<Self> {
+        match (appearance >> 6, appearance & 0x3F) {
+            (0x001, _) => Some(DeviceType::Phone),
+            (0x002, _) => Some(DeviceType::Computer),
+            (0x003, _) => Some(DeviceType::Watch),
+            (0x006, _) => Some(DeviceType::RemoteControl),
+            // Human Interface Device
+            (0x00F, 0x01) => Some(DeviceType::Keyboard),
+            (0x00F, 0x02) => Some(DeviceType::Mouse),
+            (0x00F, 0x03 | 0x04) => Some(DeviceType::Gamepad),
+            (0x00F, 0x05 | 0x09) => Some(DeviceType::Trackpad),
+            (0x00F, 0x07) => Some(DeviceType::Stylus),
+            (0x00F, 0x0A) => Some(DeviceType::RemoteControl),
+            // Audio Sink
+            (0x021, _) => Some(DeviceType::Speaker),
+            // Wearable Audio Device
+            (0x025, 0x01) => Some(DeviceType::Earbuds),
+            (0x025, 0x02) => Some(DeviceType::Headset),
+            (0x025, 0x03 | 0x04) => Some(DeviceType::Headphones),
+            (0x029, _) => Some(DeviceType::HearingAid),
+            _ => None,
+        }
+    }
+
+    /// From the Class of Device of a Classic device (major class in bits
+    /// 12-8, minor class in bits 7-2). Uncategorized classes give `None`.
+    pub fn from_class_of_device(class_of_device: u32) -> Option<Self> {
+        let major = (class_of_device >> 8) & 0x1F;
+        let minor = (class_of_device >> 2) & 0x3F;
+        match major {
+            0x01 => Some(DeviceType::Computer),
+            0x02 => Some(DeviceType::Phone),
+            // Audio/Video
+            0x04 => match minor {
+                0x01 | 0x02 => Some(DeviceType::Headset),
+                0x05 | 0x0A => Some(DeviceType::Speaker),
+                0x06 => Some(DeviceType::Headphones),
+                _ => None,
+            },
+            // Peripheral: keyboard/pointing in the top two bits, the kind below
+            0x05 => match (minor >> 4, minor & 0x0F) {
+                (_, 0x01 | 0x02) => Some(DeviceType::Gamepad),
+                (_, 0x03) => Some(DeviceType::RemoteControl),
+                (_, 0x05) => Some(DeviceType::Trackpad),
+                (_, 0x07) => Some(DeviceType::Stylus),
+                (0b01 | 0b11, _) => Some(DeviceType::Keyboard),
+                (0b10, _) => Some(DeviceType::Mouse),
+                _ => None,
+            },
+            // Wearable
+            0x07 => match minor {
+                0x01 => Some(DeviceType::Watch),
+                _ => None,
+            },
+            // Toy
+            0x08 => match minor {
+                0x04 => Some(DeviceType::Gamepad),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Last resort for devices that report neither: generic words in the name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        const KEYWORDS: &[(&str, DeviceType)] = &[
+            ("hearing", DeviceType::HearingAid),
+            ("trackpad", DeviceType::Trackpad),
+            ("touchpad", DeviceType::Trackpad),
+            ("mouse", DeviceType::Mouse),
+            ("keyboard", DeviceType::Keyboard),
+            ("controller", DeviceType::Gamepad),
+            ("gamepad", DeviceType::Gamepad),
+            ("pencil", DeviceType::Stylus),
+            ("stylus", DeviceType::Stylus),
+            ("headset", DeviceType::Headset),
+            ("headphone", DeviceType::Headphones),
+            ("earphone", DeviceType::Earbuds),
+            ("earbud", DeviceType::Earbuds),
+            ("buds", DeviceType::Earbuds),
+            ("speaker", DeviceType::Speaker),
+            ("watch", DeviceType::Watch),
+        ];
+
+        let name = name.to_lowercase();
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| name.contains(keyword))
+            .map(|(_, device_type)| *device_type)
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.label())
+    }
+}
+
+impl FromStr for DeviceType {
+    type Err = anyhow::Error;
+
+    /// Accepts the label or the variant name in any case, e.g. `hearing aid`
+    /// or `HearingAid`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let wanted: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        Self::ALL
+            .into_iter()
+            .find(|device_type| {
+                wanted.eq_ignore_ascii_case(&device_type.label().replace(' ', ""))
+                    || wanted.eq_ignore_ascii_case(&format!("{:?}", device_type))
+            })
+            .ok_or_else(|| anyhow!("Unknown device type '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_class_of_device() {
+        // Examples of common devices' Class of Device values
+        assert_eq!(DeviceType::from_class_of_device(0x240404), Some(DeviceType::Headset));
+        assert_eq!(DeviceType::from_class_of_device(0x240418), Some(DeviceType::Headphones));
+        assert_eq!(DeviceType::from_class_of_device(0x240414), Some(DeviceType::Speaker));
+        assert_eq!(DeviceType::from_class_of_device(0x002580), Some(DeviceType::Mouse));
+        assert_eq!(DeviceType::from_class_of_device(0x002540), Some(DeviceType::Keyboard));
+        assert_eq!(DeviceType::from_class_of_device(0x002594), Some(DeviceType::Trackpad));
+        assert_eq!(DeviceType::from_class_of_device(0x002508), Some(DeviceType::Gamepad));
+        assert_eq!(DeviceType::from_class_of_device(0x000704), Some(DeviceType::Watch));
+        assert_eq!(DeviceType::from_class_of_device(0x5A020C), Some(DeviceType::Phone));
+        assert_eq!(DeviceType::from_class_of_device(0x001F00), None);
+    }
+
+    #[test]
+    fn test_from_appearance() {
+        assert_eq!(DeviceType::from_appearance(0x03C2), Some(DeviceType::Mouse));
+        assert_eq!(DeviceType::from_appearance(0x03C4), Some(DeviceType::Gamepad));
+        assert_eq!(DeviceType::from_appearance(0x03C7), Some(DeviceType::Stylus));
+        assert_eq!(DeviceType::from_appearance(0x03C9), Some(DeviceType::Trackpad));
+        assert_eq!(DeviceType::from_appearance(0x0941), Some(DeviceType::Earbuds));
+        assert_eq!(DeviceType::from_appearance(0x0942), Some(DeviceType::Headset));
+        assert_eq!(DeviceType::from_appearance(0x0A42), Some(DeviceType::HearingAid));
+        assert_eq!(DeviceType::from_appearance(0x00C2), Some(DeviceType::Watch));
+        // Generic HID and unknown say nothing specific
+        assert_eq!(DeviceType::from_appearance(0x03C0), None);
+        assert_eq!(DeviceType::from_appearance(0x0000), None);
+    }
+
+    #[test]
+    fn test_classify_prefers_reported_values_over_name() {
+        assert_eq!(DeviceType::classify(Some(0x002580), Some(0x03C1), "Mouse"), DeviceType::Keyboard);
+        assert_eq!(DeviceType::classify(Some(0x240404), None, "Keyboard"), DeviceType::Headset);
+        assert_eq!(DeviceType::classify(None, Some(0x0000), "Galaxy Buds2"), DeviceType::Earbuds);
+        assert_eq!(DeviceType::classify(None, None, "MX Master 3"), DeviceType::Other);
+        assert_eq!(format!("[{:<8}]", DeviceType::Mouse), "[Mouse   ]");
+        assert_eq!("hearing aid".parse::<DeviceType>().unwrap(), DeviceType::HearingAid);
+        assert_eq!("RemoteControl".parse::<DeviceType>().unwrap(), DeviceType::RemoteControl);
+        assert!("toaster".parse::<DeviceType>().is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::bluetooth_battery::{BatteryComponent, BatteryResult};
+use crate::device_type::DeviceType;
+use crate::estimator::Estimate;
+use crate::rfcomm_profile::RfcommProfile;
+
+/// A device as shown to the user: identity, current level and estimate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BluetoothDevice {
+    pub name: String,
+    pub mac_address: String,
+    pub device_type: DeviceType,
+    pub battery_level: Option<u8>,
+    /// Per-component levels for devices that report them (e.g. earbuds).
+    pub battery: BatteryResult,
+    pub battery_estimate: String,
+    /// Estimated minutes left, when there is an estimate at all.
+    pub remaining_minutes: Option<u64>,
+    pub accuracy: String,
+    /// The device's own low-battery threshold from the registry, if set.
+    pub low_battery_threshold: Option<u8>,
+    /// Left/right/case levels with their own estimates; empty for devices
+    /// with a single battery.
+    pub components: Vec<ComponentStatus>,
+    /// The RFCOMM service the level was read over, if any.
+    pub profile: Option<RfcommProfile>,
+    /// Image from the device registry, shown next to the device.
+    pub icon: Option<PathBuf>,
+}
+
+/// One separately reported battery of a device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentStatus {
+    pub component: BatteryComponent,
+    pub level: u8,
+    pub battery_estimate: String,
+    pub remaining_minutes: u64,
+    pub accuracy: String,
+}
+
+impl ComponentStatus {
+    pub fn new(component: BatteryComponent, level: u8, estimate: &Estimate) -> Self {
+        Self {
+            component,
+            level,
+            battery_estimate: estimate.format_remaining(),
+            remaining_minutes: estimate.remaining.as_secs() / 60,
+            accuracy: estimate.accuracy.label().to_string(),
+        }
+    }
+}
+
+impl BluetoothDevice {
+    pub fn component(&self, component: BatteryComponent) -> Option<&ComponentStatus> {
+        self.components.iter().find(|c| c.component == component)
+    }
+
+    /// Lowest level reported by any component, so a flat left earbud
+    /// counts even when the case is full.
+    pub fn lowest_level(&self) -> Option<u8> {
+        [
+            self.battery_level,
+            self.battery.overall,
+            self.battery.left,
+            self.battery.right,
+            self.battery.case,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Whether any component is below the device's own threshold, or
+    /// `default_threshold` if it has none.
+    pub fn is_low_battery(&self, default_threshold: u8) -> bool {
+        self.lowest_level().is_some_and(|level| self.is_low_level(level, default_threshold))
+    }
+
+    /// Whether `level` of this device or one of its components is below the
+    /// device's own threshold, or `default_threshold` if it has none.
+    pub fn is_low_level(&self, level: u8, default_threshold: u8) -> bool {
+        level < self.low_battery_threshold.unwrap_or(default_threshold)
+    }
+}
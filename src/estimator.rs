@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use crate::battery_history::BatterySample;
+
+/// Only samples this close to the newest one take part in the fit.
+pub const ESTIMATION_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Upper bound on samples fed to the regression (it is O(n^2)).
+const MAX_FIT_SAMPLES: usize = 200;
+
+/// Minimum observed time span before a rate is trusted at all.
+const MIN_APPROXIMATE_SPAN: Duration = Duration::from_secs(10 * 60);
+
+/// Time span after which an estimate is reported as "Estimated".
+const MIN_ESTIMATED_SPAN: Duration = Duration::from_secs(60 * 60);
+
+/// A rise of more than this many points is treated as a charge rather than
+/// reading jitter.
+const CHARGE_THRESHOLD: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accuracy {
+    Measuring,
+    Approximate,
+    Estimated,
+}
+
+impl Accuracy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Accuracy::Measuring => "Measuring",
+            Accuracy::Approximate => "Approximate",
+            Accuracy::Estimated => "Estimated",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub remaining: Duration,
+    pub accuracy: Accuracy,
+    /// Observed drain in percent per hour, if enough data was available.
+    pub drain_per_hour: Option<f64>,
+}
+
+impl Estimate {
+    pub fn format_remaining(&self) -> String {
+        format_duration(self.remaining)
+    }
+}
+
+/// Estimates the remaining runtime from the observed discharge rate.
+///
+/// Only the trailing discharge segment is used: a rise beyond reading jitter
+/// (a charge) restarts the fit. Until the segment spans enough time and shows at least
+/// one drop, the fixed lookup table is used and accuracy stays `Measuring`.
+pub fn estimate_remaining(samples: &[BatterySample], current_level: u8) -> Estimate {
+    let segment = discharge_segment(samples);
+
+    if let Some(drain_per_hour) = drain_rate_per_hour(segment) {
+        let span = Duration::from_secs(segment[segment.len() - 1].timestamp - segment[0].timestamp);
+        let distinct_levels = count_distinct_levels(segment);
+
+        let accuracy = if span >= MIN_ESTIMATED_SPAN && distinct_levels >= 3 {
+            Accuracy::Estimated
+        } else if span >= MIN_APPROXIMATE_SPAN && distinct_levels >= 2 {
+            Accuracy::Approximate
+        } else {
+            Accuracy::Measuring
+        };
+
+        if accuracy != Accuracy::Measuring {
+            let hours = current_level as f64 / drain_per_hour;
+            return Estimate {
+                remaining: Duration::from_secs((hours * 3600.0).round() as u64),
+                accuracy,
+                drain_per_hour: Some(drain_per_hour),
+            };
+        }
+    }
+
+    table_estimate(current_level)
+}
+
+/// Fallback used while there is not enough history for a rate.
+pub fn table_estimate(battery_level: u8) -> Estimate {
+    let minutes = calculate_hours_from_battery(battery_level) as u64 * 60
+        + calculate_minutes_from_battery(battery_level) as u64;
+    Estimate {
+        remaining: Duration::from_secs(minutes * 60),
+        accuracy: Accuracy::Measuring,
+        drain_per_hour: None,
+    }
+}
+
+/// Fallback for a device with a known runtime on a full charge, assuming
+/// the level drains linearly.
+pub fn rated_estimate(rated_runtime: Duration, battery_level: u8) -> Estimate {
+    Estimate {
+        remaining: rated_runtime.mul_f64(battery_level.min(100) as f64 / 100.0),
+        accuracy: Accuracy::Measuring,
+        drain_per_hour: None,
+    }
+}
+
+pub fn calculate_hours_from_battery(battery_level: u8) -> u8 {
+    match battery_level {
+        90..=100 => 8,
+        80..=89 => 7,
+        70..=79 => 6,
+        60..=69 => 5,
+        50..=59 => 4,
+        40..=49 => 3,
+        30..=39 => 2,
+        20..=29 => 1,
+        _ => 0,
+    }
+}
+
+pub fn calculate_minutes_from_battery(battery_level: u8) -> u8 {
+    ((battery_level % 10) * 6) % 60
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let total_minutes = duration.as_secs() / 60;
+    let days = total_minutes / (24 * 60);
+    let hours = (total_minutes / 60) % 24;
+    let minutes = total_minutes % 60;
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else {
+        format!("{}h {}m", hours, minutes)
+    }
+}
+
+/// Returns the samples after the most recent charge, limited to the
+/// estimation window and `MAX_FIT_SAMPLES`.
+fn discharge_segment(samples: &[BatterySample]) -> &[BatterySample] {
+    let Some(newest) = samples.last() else {
+        return samples;
+    };
+
+    let window_start = newest.timestamp.saturating_sub(ESTIMATION_WINDOW.as_secs());
+    let mut start = samples
+        .iter()
+        .position(|s| s.timestamp >= window_start)
+        .unwrap_or(samples.len());
+
+    // Compare against the two preceding samples so a single low misread is
+    // not mistaken for a charge.
+    for i in (start + 1..samples.len()).rev() {
+        let previous = if i >= 2 {
+            samples[i - 1].level.max(samples[i - 2].level)
+        } else {
+            samples[i - 1].level
+        };
+        if samples[i].level > previous.saturating_add(CHARGE_THRESHOLD) {
+            start = i;
+            break;
+        }
+    }
+
+    start = start.max(samples.len().saturating_sub(MAX_FIT_SAMPLES));
+    &samples[start..]
+}
+
+/// Theil-Sen slope (median of pairwise slopes) in percent per hour, positive
+/// when draining. Robust against the odd misread level.
+fn drain_rate_per_hour(samples: &[BatterySample]) -> Option<f64> {
+    let mut slopes = Vec::new();
+    for (i, a) in samples.iter().enumerate() {
+        for b in &samples[i + 1..] {
+            if b.timestamp > a.timestamp {
+                let dt_hours = (b.timestamp - a.timestamp) as f64 / 3600.0;
+                slopes.push((b.level as f64 - a.level as f64) / dt_hours);
+            }
+        }
+    }
+
+    if slopes.is_empty() {
+        return None;
+    }
+
+    slopes.sort_by(|a, b| a.total_cmp(b));
+    let mid = slopes.len() / 2;
+    let median = if slopes.len() % 2 == 0 {
+        (slopes[mid - 1] + slopes[mid]) / 2.0
+    } else {
+        slopes[mid]
+    };
+
+    let drain = -median;
+    if drain > 0.0 {
+        Some(drain)
+    } else {
+        None
+    }
+}
+
+fn count_distinct_levels(samples: &[BatterySample]) -> usize {
+    let mut levels: Vec<u8> = samples.iter().map(|s| s.level).collect();
+    levels.sort_unstable();
+    levels.dedup();
+    levels.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery_history::{BatteryBackend, ConnectionState};
+    use crate::bluetooth_battery::BatteryComponent;
+
+    fn sample(minutes: u64, level: u8) -> BatterySample {
+        BatterySample {
+            timestamp: 1_700_000_000 + minutes * 60,
+            level,
+            source: BatteryBackend::Uwp,
+            connection: ConnectionState::Connected,
+            component: BatteryComponent::Overall,
+        }
+    }
+
+    #[test]
+    fn test_falls_back_to_table_without_history() {
+        let estimate = estimate_remaining(&[], 85);
+        assert_eq!(estimate.accuracy, Accuracy::Measuring);
+        assert_eq!(estimate.format_remaining(), "7h 30m");
+        assert_eq!(estimate.drain_per_hour, None);
+
+        let estimate = rated_estimate(Duration::from_secs(30 * 3600), 50);
+        assert_eq!(estimate.format_remaining(), "15h 0m");
+    }
+
+    #[test]
+    fn test_stable_level_keeps_measuring() {
+        let samples = [sample(0, 80), sample(30, 80), sample(90, 80)];
+        let estimate = estimate_remaining(&samples, 80);
+        assert_eq!(estimate.accuracy, Accuracy::Measuring);
+    }
+
+    #[test]
+    fn test_fast_drain_earbuds() {
+        // 20% per hour
+        let samples: Vec<_> = (0..=6).map(|i| sample(i * 15, 100 - (i * 5) as u8)).collect();
+        let estimate = estimate_remaining(&samples, 70);
+        assert_eq!(estimate.accuracy, Accuracy::Estimated);
+        assert!((estimate.drain_per_hour.unwrap() - 20.0).abs() < 0.01);
+        assert_eq!(estimate.format_remaining(), "3h 30m");
+    }
+
+    #[test]
+    fn test_slow_drain_mouse() {
+        // 1% per day over four days
+        let samples: Vec<_> = (0..=4).map(|d| sample(d * 24 * 60, 90 - d as u8)).collect();
+        let estimate = estimate_remaining(&samples, 86);
+        assert_eq!(estimate.accuracy, Accuracy::Estimated);
+        assert_eq!(estimate.format_remaining(), "86d 0h");
+    }
+
+    #[test]
+    fn test_outlier_does_not_skew_rate() {
+        let samples = [
+            sample(0, 100),
+            sample(30, 90),
+            sample(60, 80),
+            sample(75, 20), // misread
+            sample(90, 70),
+            sample(120, 60),
+        ];
+        let estimate = estimate_remaining(&samples, 60);
+        assert!((estimate.drain_per_hour.unwrap() - 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_charge_restarts_segment() {
+        let samples = [
+            sample(0, 50),
+            sample(60, 10),
+            sample(120, 100), // charged
+            sample(125, 100),
+        ];
+        let estimate = estimate_remaining(&samples, 100);
+        assert_eq!(estimate.accuracy, Accuracy::Measuring);
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::address::BluetoothAddress;
+use crate::device_type::DeviceType;
+use crate::devices::BluetoothDevice;
+
+/// One entry of an allow or deny list: `type:<device type>`, a device
+/// address, or otherwise part of the name (case-insensitive).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterRule {
+    Address(BluetoothAddress),
+    Type(DeviceType),
+    Name(String),
+}
+
+impl FilterRule {
+    pub fn matches(&self, name: &str, mac_address: &str, device_type: DeviceType) -> bool {
+        match self {
+            FilterRule::Address(address) => mac_address.parse::<BluetoothAddress>().is_ok_and(|a| a == *address),
+            FilterRule::Type(wanted) => device_type == *wanted,
+            FilterRule::Name(part) => name.to_lowercase().contains(&part.to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for FilterRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(device_type) = s.strip_prefix("type:") {
+            return Ok(FilterRule::Type(device_type.parse()?));
+        }
+        if let Ok(address) = s.parse() {
+            return Ok(FilterRule::Address(address));
+        }
+        if s.is_empty() {
+            return Err(anyhow::anyhow!("Empty filter rule"));
+        }
+        Ok(FilterRule::Name(s.to_string()))
+    }
+}
+
+impl fmt::Display for FilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterRule::Address(address) => write!(f, "{}", address),
+            FilterRule::Type(device_type) => write!(f, "type:{:?}", device_type),
+            FilterRule::Name(part) => write!(f, "{}", part),
+        }
+    }
+}
+
+impl Serialize for FilterRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FilterRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which devices are shown. The default shows every device any backend
+/// finds, whatever its type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceFilter {
+    /// When not empty, only devices matching one of these are shown.
+    pub allow: Vec<FilterRule>,
+    /// Devices matching any of these are hidden, even if allowed.
+    pub deny: Vec<FilterRule>,
+    /// Hide devices that did not report a battery level.
+    pub only_with_battery: bool,
+}
+
+impl DeviceFilter {
+    /// Whether a device passes the allow and deny lists, so its battery is
+    /// worth reading. `name` and `device_type` are the ones shown.
+    pub fn admits(&self, name: &str, mac_address: &str, device_type: DeviceType) -> bool {
+        let matches = |rule: &FilterRule| rule.matches(name, mac_address, device_type);
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+
+    /// Whether a device is shown, once its battery has been read.
+    pub fn shows(&self, device: &BluetoothDevice) -> bool {
+        self.admits(&device.name, &device.mac_address, device.device_type)
+            && (!self.only_with_battery || device.lowest_level().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!("type:gamepad".parse::<FilterRule>().unwrap(), FilterRule::Type(DeviceType::Gamepad));
+        assert_eq!(
+            "00-11-22-33-44-55".parse::<FilterRule>().unwrap(),
+            FilterRule::Address("00:11:22:33:44:55".parse().unwrap())
+        );
+        assert_eq!("Pencil".parse::<FilterRule>().unwrap(), FilterRule::Name("Pencil".to_string()));
+        assert!("type:toaster".parse::<FilterRule>().is_err());
+        assert!(" ".parse::<FilterRule>().is_err());
+    }
+
+    #[test]
+    fn test_allow_and_deny_lists() {
+        let tracker = ("Tracker", "00:11:22:33:44:55", DeviceType::Other);
+        let gamepad = ("Pad", "66:77:88:99:AA:BB", DeviceType::Gamepad);
+        let admits = |filter: &DeviceFilter, (name, address, device_type): (&str, &str, DeviceType)| {
+            filter.admits(name, address, device_type)
+        };
+
+        // Nothing is hidden by default, not even unclassified devices
+        assert!(admits(&DeviceFilter::default(), tracker));
+
+        let filter = DeviceFilter {
+            allow: vec!["type:gamepad".parse().unwrap(), "track".parse().unwrap()],
+            deny: vec!["66:77:88:99:aa:bb".parse().unwrap()],
+            only_with_battery: false,
+        };
+        assert!(admits(&filter, tracker));
+        assert!(!admits(&filter, gamepad));
+        assert!(!admits(&filter, ("Mouse", "CC:DD:EE:FF:00:11", DeviceType::Mouse)));
+    }
+}
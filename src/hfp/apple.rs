@@ -0,0 +1,186 @@
+use std::fmt;
+
+/// Feature bits of `AT+XAPL` and its `+XAPL` response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XaplFeatures(pub u32);
+
+impl XaplFeatures {
+    pub const BATTERY_REPORTING: Self = Self(0x02);
+    pub const DOCK_STATE: Self = Self(0x04);
+    pub const SIRI_STATUS: Self = Self(0x08);
+    pub const NOISE_REDUCTION_STATUS: Self = Self(0x10);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// `AT+XAPL=<vendor>-<product>-<version>,<features>`, sent by an accessory
+/// to announce the Apple extensions it supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XaplCommand {
+    /// Hexadecimal in the command.
+    pub vendor_id: u16,
+    /// Hexadecimal in the command.
+    pub product_id: u16,
+    pub version: String,
+    pub features: XaplFeatures,
+}
+
+impl XaplCommand {
+    pub fn parse(line: &str) -> Option<Self> {
+        let body = line.trim().strip_prefix("AT+XAPL=")?;
+        let (ids, features) = body.split_once(',')?;
+        let mut ids = ids.splitn(3, '-');
+
+        Some(Self {
+            vendor_id: u16::from_str_radix(ids.next()?, 16).ok()?,
+            product_id: u16::from_str_radix(ids.next()?, 16).ok()?,
+            version: ids.next()?.to_string(),
+            features: XaplFeatures(features.trim().parse().ok()?),
+        })
+    }
+
+    /// The gateway's answer: the features it will use out of those offered.
+    pub fn response(&self, supported: XaplFeatures) -> String {
+        format!("+XAPL=iPhone,{}", self.features.0 & supported.0)
+    }
+}
+
+impl fmt::Display for XaplCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "AT+XAPL={:04X}-{:04X}-{},{}",
+            self.vendor_id, self.product_id, self.version, self.features.0
+        )
+    }
+}
+
+/// `+XAPL=<device>,<features>`: the gateway's name and the features it
+/// accepted.
+pub fn parse_xapl_response(line: &str) -> Option<(String, XaplFeatures)> {
+    let body = line.trim().strip_prefix("+XAPL=")?;
+    let (device, features) = body.rsplit_once(',')?;
+    Some((device.to_string(), XaplFeatures(features.trim().parse().ok()?)))
+}
+
+/// The state reported by one `+IPHONEACCEV`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessoryEvent {
+    /// Percent, in steps of 10.
+    pub battery: Option<u8>,
+    pub docked: Option<bool>,
+}
+
+const KEY_BATTERY: u32 = 1;
+const KEY_DOCK_STATE: u32 = 2;
+
+impl AccessoryEvent {
+    /// Parses `AT+IPHONEACCEV=<count>,<key>,<value>,...` (or the same payload
+    /// after `+IPHONEACCEV:`). The count must match the pairs that follow.
+    /// Unknown keys are skipped.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let body = line
+            .strip_prefix("AT+IPHONEACCEV=")
+            .or_else(|| line.strip_prefix("+IPHONEACCEV:"))?;
+        let numbers: Vec<u32> = body
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect::<Option<_>>()?;
+
+        let (count, pairs) = numbers.split_first()?;
+        if pairs.len() != *count as usize * 2 {
+            return None;
+        }
+
+        let mut event = Self::default();
+        for pair in pairs.chunks_exact(2) {
+            match (pair[0], pair[1]) {
+                (KEY_BATTERY, value @ 0..=9) => event.battery = Some((value as u8 + 1) * 10),
+                (KEY_DOCK_STATE, value @ 0..=1) => event.docked = Some(value == 1),
+                (KEY_BATTERY | KEY_DOCK_STATE, _) => return None,
+                _ => {}
+            }
+        }
+        Some(event)
+    }
+
+    /// The command an accessory sends for this state. Battery levels are
+    /// rounded up to the next step of 10, as (0-9) can only express 10-100%.
+    pub fn to_command(&self) -> String {
+        let mut pairs = Vec::new();
+        if let Some(battery) = self.battery {
+            let value = (battery.div_ceil(10).max(1) - 1).min(9);
+            pairs.push(format!("{},{}", KEY_BATTERY, value));
+        }
+        if let Some(docked) = self.docked {
+            pairs.push(format!("{},{}", KEY_DOCK_STATE, docked as u8));
+        }
+
+        let mut command = format!("AT+IPHONEACCEV={}", pairs.len());
+        for pair in pairs {
+            command.push(',');
+            command.push_str(&pair);
+        }
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xapl_round_trip() {
+        let command = XaplCommand::parse("AT+XAPL=004C-1234-0100,10").unwrap();
+        assert_eq!(command.vendor_id, 0x004C);
+        assert_eq!(command.product_id, 0x1234);
+        assert_eq!(command.version, "0100");
+        assert!(command.features.contains(XaplFeatures::BATTERY_REPORTING));
+        assert!(command.features.contains(XaplFeatures::SIRI_STATUS));
+        assert_eq!(command.to_string(), "AT+XAPL=004C-1234-0100,10");
+
+        let supported = XaplFeatures::BATTERY_REPORTING.union(XaplFeatures::DOCK_STATE);
+        assert_eq!(command.response(supported), "+XAPL=iPhone,2");
+        assert_eq!(
+            parse_xapl_response(&command.response(supported)),
+            Some(("iPhone".to_string(), XaplFeatures::BATTERY_REPORTING))
+        );
+        assert!(XaplCommand::parse("AT+XAPL=004C,10").is_none());
+    }
+
+    #[test]
+    fn test_iphoneaccev_key_value_pairs() {
+        // Battery is key 1 whatever its position
+        let event = AccessoryEvent::parse("+IPHONEACCEV: 2,1,5,2,0").unwrap();
+        assert_eq!(event, AccessoryEvent { battery: Some(60), docked: Some(false) });
+
+        let event = AccessoryEvent::parse("AT+IPHONEACCEV=2,2,1,1,9").unwrap();
+        assert_eq!(event, AccessoryEvent { battery: Some(100), docked: Some(true) });
+
+        // Count disagrees with the pairs, or out-of-range values
+        assert!(AccessoryEvent::parse("AT+IPHONEACCEV=2,1,5").is_none());
+        assert!(AccessoryEvent::parse("AT+IPHONEACCEV=1,1,10").is_none());
+        // Unknown keys are ignored
+        assert_eq!(AccessoryEvent::parse("AT+IPHONEACCEV=1,7,3").unwrap(), AccessoryEvent::default());
+    }
+
+    #[test]
+    fn test_iphoneaccev_round_trip() {
+        for command in ["AT+IPHONEACCEV=1,1,0", "AT+IPHONEACCEV=2,1,9,2,1", "AT+IPHONEACCEV=1,2,0"] {
+            assert_eq!(AccessoryEvent::parse(command).unwrap().to_command(), command);
+        }
+        for level in (10..=100).step_by(10) {
+            let event = AccessoryEvent { battery: Some(level), docked: None };
+            assert_eq!(AccessoryEvent::parse(&event.to_command()), Some(event));
+        }
+        let low = AccessoryEvent { battery: Some(3), docked: None };
+        assert_eq!(low.to_command(), "AT+IPHONEACCEV=1,1,0");
+    }
+}
@@ -0,0 +1,79 @@
+/// Supported features a Hands-Free unit announces with `AT+BRSF=<bits>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HfFeatures(pub u32);
+
+impl HfFeatures {
+    pub const EC_NR: Self = Self(1 << 0);
+    pub const THREE_WAY_CALLING: Self = Self(1 << 1);
+    pub const CLI_PRESENTATION: Self = Self(1 << 2);
+    pub const VOICE_RECOGNITION: Self = Self(1 << 3);
+    pub const REMOTE_VOLUME: Self = Self(1 << 4);
+    pub const ENHANCED_CALL_STATUS: Self = Self(1 << 5);
+    pub const ENHANCED_CALL_CONTROL: Self = Self(1 << 6);
+    pub const CODEC_NEGOTIATION: Self = Self(1 << 7);
+    pub const HF_INDICATORS: Self = Self(1 << 8);
+    pub const ESCO_S4: Self = Self(1 << 9);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Supported features an Audio Gateway answers with `+BRSF: <bits>`. The
+/// bit positions differ from the HF's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgFeatures(pub u32);
+
+impl AgFeatures {
+    pub const THREE_WAY_CALLING: Self = Self(1 << 0);
+    pub const EC_NR: Self = Self(1 << 1);
+    pub const VOICE_RECOGNITION: Self = Self(1 << 2);
+    pub const IN_BAND_RING: Self = Self(1 << 3);
+    pub const VOICE_TAG: Self = Self(1 << 4);
+    pub const REJECT_CALL: Self = Self(1 << 5);
+    pub const ENHANCED_CALL_STATUS: Self = Self(1 << 6);
+    pub const ENHANCED_CALL_CONTROL: Self = Self(1 << 7);
+    pub const EXTENDED_ERROR_CODES: Self = Self(1 << 8);
+    pub const CODEC_NEGOTIATION: Self = Self(1 << 9);
+    pub const HF_INDICATORS: Self = Self(1 << 10);
+    pub const ESCO_S4: Self = Self(1 << 11);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Bits of an `AT+BRSF=` command or `+BRSF:` response.
+pub fn parse_brsf(line: &str) -> Option<u32> {
+    let line = line.trim();
+    let body = line
+        .strip_prefix("AT+BRSF=")
+        .or_else(|| line.strip_prefix("+BRSF:"))?;
+    body.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_bits_differ_by_role() {
+        assert_eq!(HfFeatures::HF_INDICATORS.0, 256);
+        assert_eq!(AgFeatures::HF_INDICATORS.0, 1024);
+
+        let ag = AgFeatures(parse_brsf("+BRSF: 1639").unwrap());
+        assert!(ag.contains(AgFeatures::HF_INDICATORS));
+        assert!(ag.contains(AgFeatures::CODEC_NEGOTIATION));
+        assert!(!ag.contains(AgFeatures::IN_BAND_RING));
+        assert_eq!(parse_brsf("AT+BRSF=256"), Some(256));
+        assert_eq!(parse_brsf("+BRSF: x"), None);
+    }
+}
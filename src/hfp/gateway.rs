@@ -0,0 +1,337 @@
+use super::apple::{AccessoryEvent, XaplCommand, XaplFeatures};
+use super::features::{self, AgFeatures, HfFeatures};
+use super::hf_indicators;
+use super::vendor::VendorRegistry;
+
+/// Indicators announced in `+CIND=?`, with the values we report for them.
+/// We have no calls or network, so only service, signal and battery are set.
+const AG_INDICATORS: &[(&str, &str, u32)] = &[
+    ("service", "0,1", 1),
+    ("call", "0,1", 0),
+    ("callsetup", "0-3", 0),
+    ("callheld", "0-2", 0),
+    ("signal", "0-5", 5),
+    ("roam", "0,1", 0),
+    ("battchg", "0-5", 5),
+];
+
+/// Settings an HF may send during setup, and the Headset profile's button
+/// press, that we accept without acting on.
+const IGNORED_SETTINGS: &[&str] = &[
+    "AT+VGS=", "AT+VGM=", "AT+CLIP=", "AT+CCWA=", "AT+CMEE=", "AT+BIA=", "AT+BAC=", "AT+CKPD=",
+];
+
+/// What the gateway needs done after a command from the HF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GatewayEvent {
+    /// Bytes to write back to the HF.
+    Send(String),
+    /// The service level connection is up; the HF may now report battery.
+    ServiceLevelConnected,
+    Battery(u8),
+}
+
+/// The Audio Gateway side of HFP. In HFP the headset (HF) sends the AT
+/// commands, so to hear its battery reports we answer its service level
+/// connection setup like a phone would, announcing Apple battery reporting
+/// and the HF battery indicator.
+#[derive(Debug)]
+pub struct AudioGateway {
+    features: AgFeatures,
+    hf_features: Option<HfFeatures>,
+    /// HF indicators the HF listed in `AT+BIND=`.
+    hf_indicators: Vec<u16>,
+    connected: bool,
+    battery: Option<u8>,
+    vendors: VendorRegistry,
+    pending: String,
+}
+
+impl AudioGateway {
+    pub const FEATURES: AgFeatures = AgFeatures::HF_INDICATORS.union(AgFeatures::EXTENDED_ERROR_CODES);
+    /// Apple extensions we take up when offered in `AT+XAPL`.
+    pub const XAPL_FEATURES: XaplFeatures = XaplFeatures::BATTERY_REPORTING;
+    /// The HF indicators we enable.
+    pub const HF_INDICATORS: &'static [u16] = &[hf_indicators::BATTERY_LEVEL];
+
+    pub fn new() -> Self {
+        Self {
+            features: Self::FEATURES,
+            hf_features: None,
+            hf_indicators: Vec::new(),
+            connected: false,
+            battery: None,
+            vendors: VendorRegistry::builtin(),
+            pending: String::new(),
+        }
+    }
+
+    pub fn with_vendors(mut self, vendors: VendorRegistry) -> Self {
+        self.vendors = vendors;
+        self
+    }
+
+    pub fn hf_features(&self) -> Option<HfFeatures> {
+        self.hf_features
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Latest battery percentage the HF reported.
+    pub fn battery(&self) -> Option<u8> {
+        self.battery
+    }
+
+    /// Processes received text and returns what to do about the complete
+    /// commands in it. Commands end with a carriage return.
+    pub fn feed(&mut self, data: &str) -> Vec<GatewayEvent> {
+        self.pending.push_str(data);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.pending.find(['\r', '\n']) {
+            let line: String = self.pending.drain(..=end).collect();
+            let line = line.trim();
+            if !line.is_empty() {
+                events.extend(self.handle_command(line));
+            }
+        }
+        events
+    }
+
+    /// The reply to `command`, followed by whatever it changed.
+    fn handle_command(&mut self, command: &str) -> Vec<GatewayEvent> {
+        // Only the command name is case-insensitive; arguments keep their case
+        let command = match command.split_once('=') {
+            Some((name, arguments)) => format!("{}={}", name.to_ascii_uppercase(), arguments),
+            None => command.to_ascii_uppercase(),
+        };
+        let command = command.as_str();
+        let mut events = Vec::new();
+        let mut replies = Vec::new();
+        let mut ok = true;
+
+        if command.starts_with("AT+BRSF=") {
+            match features::parse_brsf(command) {
+                Some(bits) => {
+                    self.hf_features = Some(HfFeatures(bits));
+                    replies.push(format!("+BRSF: {}", self.features.0));
+                }
+                None => ok = false,
+            }
+        } else if command == "AT+CIND=?" {
+            replies.push(format!("+CIND: {}", indicator_layout()));
+        } else if command == "AT+CIND?" {
+            replies.push(format!("+CIND: {}", indicator_values()));
+        } else if command.starts_with("AT+CMER=") {
+            if !self.uses_hf_indicators() {
+                self.set_connected(&mut events);
+            }
+        } else if command == "AT+CHLD=?" {
+            replies.push("+CHLD: (0,1,2,3)".to_string());
+        } else if command.starts_with("AT+XAPL=") {
+            match XaplCommand::parse(command) {
+                Some(xapl) => replies.push(xapl.response(Self::XAPL_FEATURES)),
+                None => ok = false,
+            }
+        } else if command == "AT+BIND=?" {
+            let supported: Vec<String> = Self::HF_INDICATORS.iter().map(u16::to_string).collect();
+            replies.push(format!("+BIND: ({})", supported.join(",")));
+        } else if command == "AT+BIND?" {
+            for indicator in Self::HF_INDICATORS {
+                let enabled = self.hf_indicators.contains(indicator);
+                replies.push(format!("+BIND: {},{}", indicator, enabled as u8));
+            }
+            // The last step of the service level connection with HF indicators
+            self.set_connected(&mut events);
+        } else if let Some(body) = command.strip_prefix("AT+BIND=") {
+            match hf_indicators::parse_supported(body) {
+                Some(indicators) => self.hf_indicators = indicators,
+                None => ok = false,
+            }
+        } else if command.starts_with("AT+BIEV=") {
+            match hf_indicators::parse_biev(command) {
+                Some((hf_indicators::BATTERY_LEVEL, level @ 0..=100)) => self.set_battery(level as u8, &mut events),
+                Some((hf_indicators::BATTERY_LEVEL, _)) | None => ok = false,
+                Some(_) => {}
+            }
+        } else if command.starts_with("AT+IPHONEACCEV=") {
+            // A valid report may carry only the dock state
+            match AccessoryEvent::parse(command) {
+                Some(event) => {
+                    if let Some(level) = event.battery {
+                        self.set_battery(level, &mut events);
+                    }
+                }
+                None => ok = false,
+            }
+        } else if let Some(report) = self.vendors.parse(command) {
+            self.set_battery(report.level, &mut events);
+        } else if !IGNORED_SETTINGS.iter().any(|prefix| command.starts_with(prefix)) {
+            ok = false;
+        }
+
+        let mut reply = String::new();
+        for line in replies {
+            reply.push_str(&format!("\r\n{}\r\n", line));
+        }
+        reply.push_str(if ok { "\r\nOK\r\n" } else { "\r\nERROR\r\n" });
+        events.insert(0, GatewayEvent::Send(reply));
+        events
+    }
+
+    /// Whether the HF indicator exchange is part of the setup.
+    fn uses_hf_indicators(&self) -> bool {
+        self.features.contains(AgFeatures::HF_INDICATORS)
+            && self
+                .hf_features
+                .is_some_and(|features| features.contains(HfFeatures::HF_INDICATORS))
+    }
+
+    fn set_connected(&mut self, events: &mut Vec<GatewayEvent>) {
+        if !self.connected {
+            self.connected = true;
+            events.push(GatewayEvent::ServiceLevelConnected);
+        }
+    }
+
+    fn set_battery(&mut self, level: u8, events: &mut Vec<GatewayEvent>) {
+        self.battery = Some(level);
+        events.push(GatewayEvent::Battery(level));
+    }
+}
+
+impl Default for AudioGateway {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn indicator_layout() -> String {
+    AG_INDICATORS
+        .iter()
+        .map(|(name, range, _)| format!("(\"{}\",({}))", name, range))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn indicator_values() -> String {
+    AG_INDICATORS
+        .iter()
+        .map(|(_, _, value)| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hfp::indicators::IndicatorMap;
+
+    /// Commands of a headset setting up a service level connection with HF
+    /// indicators and Apple extensions, each with the reply a gateway owes it.
+    const HF_TRANSCRIPT: &[(&str, &str)] = &[
+        ("AT+BRSF=447\r", "\r\n+BRSF: 1280\r\n\r\nOK\r\n"),
+        ("AT+BAC=1,2\r", "\r\nOK\r\n"),
+        ("AT+CIND=?\r", "\r\n+CIND: (\"service\",(0,1)),(\"call\",(0,1)),(\"callsetup\",(0-3)),(\"callheld\",(0-2)),(\"signal\",(0-5)),(\"roam\",(0,1)),(\"battchg\",(0-5))\r\n\r\nOK\r\n"),
+        ("AT+CIND?\r", "\r\n+CIND: 1,0,0,0,5,0,5\r\n\r\nOK\r\n"),
+        ("AT+CMER=3,0,0,1\r", "\r\nOK\r\n"),
+        ("AT+CHLD=?\r", "\r\n+CHLD: (0,1,2,3)\r\n\r\nOK\r\n"),
+        ("AT+BIND=1,2\r", "\r\nOK\r\n"),
+        ("AT+BIND=?\r", "\r\n+BIND: (2)\r\n\r\nOK\r\n"),
+        ("AT+BIND?\r", "\r\n+BIND: 2,1\r\n\r\nOK\r\n"),
+        ("AT+XAPL=05AC-1234-0100,6\r", "\r\n+XAPL=iPhone,2\r\n\r\nOK\r\n"),
+        ("AT+VGS=9\r", "\r\nOK\r\n"),
+        ("AT+BIEV=2,64\r", "\r\nOK\r\n"),
+        ("AT+IPHONEACCEV=2,1,4,2,0\r", "\r\nOK\r\n"),
+        ("AT+XEVENT=BATTERY,9,11,300,0\r", "\r\nOK\r\n"),
+        ("AT+COPS?\r", "\r\nERROR\r\n"),
+    ];
+
+    #[test]
+    fn test_scripted_hf_transcript() {
+        let mut gateway = AudioGateway::new();
+        let mut batteries = Vec::new();
+
+        for (command, reply) in HF_TRANSCRIPT {
+            let events = gateway.feed(command);
+            assert_eq!(events[0], GatewayEvent::Send(reply.to_string()), "{}", command.trim());
+            for event in &events[1..] {
+                match event {
+                    GatewayEvent::ServiceLevelConnected => assert_eq!(*command, "AT+BIND?\r"),
+                    GatewayEvent::Battery(level) => batteries.push(*level),
+                    GatewayEvent::Send(_) => panic!("two replies to {}", command.trim()),
+                }
+            }
+        }
+
+        assert!(gateway.hf_features().unwrap().contains(HfFeatures::HF_INDICATORS));
+        assert!(gateway.is_connected());
+        assert_eq!(batteries, [64, 50, 90]);
+        assert_eq!(gateway.battery(), Some(90));
+    }
+
+    #[test]
+    fn test_setup_without_hf_indicators_ends_at_cmer() {
+        let mut gateway = AudioGateway::new();
+        let events = gateway.feed("AT+BRSF=0\rAT+CIND=?\rAT+CIND?\rAT+CMER=3,0,0,1\r");
+
+        assert_eq!(events.last(), Some(&GatewayEvent::ServiceLevelConnected));
+        assert_eq!(events.len(), 5);
+    }
+
+    #[test]
+    fn test_commands_are_case_insensitive() {
+        let mut gateway = AudioGateway::new();
+        let events = gateway.feed(
+            "at+brsf=256\rat+bind=1,2\rat+bind?\rat+xapl=05ac-1234-0100,2\r\
+             at+iphoneaccev=1,1,5\rat+xevent=BATTERY,3,5,0,0\r",
+        );
+
+        let send = |reply: &str| GatewayEvent::Send(reply.to_string());
+        assert_eq!(
+            events,
+            [
+                send("\r\n+BRSF: 1280\r\n\r\nOK\r\n"),
+                send("\r\nOK\r\n"),
+                send("\r\n+BIND: 2,1\r\n\r\nOK\r\n"),
+                GatewayEvent::ServiceLevelConnected,
+                send("\r\n+XAPL=iPhone,2\r\n\r\nOK\r\n"),
+                send("\r\nOK\r\n"),
+                GatewayEvent::Battery(60),
+                send("\r\nOK\r\n"),
+                GatewayEvent::Battery(75),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_announced_indicators_parse() {
+        let map = IndicatorMap::parse(&indicator_layout()).unwrap();
+        let values = crate::hfp::indicators::parse_values(&indicator_values()).unwrap();
+        assert_eq!(map.battery_from_values(&values), Some(100));
+    }
+
+    #[test]
+    fn test_invalid_battery_reports_are_rejected() {
+        let mut gateway = AudioGateway::new();
+        let events = gateway.feed(
+            "AT+BIEV=2,140\rAT+BIEV=1,1\rAT+IPHONEACCEV=1,2,1\rAT+IPHONEACCEV=2,1,4\rAT+XEVENT=BATTERY,x,5\r",
+        );
+
+        let replies: Vec<&GatewayEvent> = events.iter().collect();
+        assert_eq!(
+            replies,
+            [
+                &GatewayEvent::Send("\r\nERROR\r\n".to_string()),
+                &GatewayEvent::Send("\r\nOK\r\n".to_string()),
+                // Only the dock state, which is fine
+                &GatewayEvent::Send("\r\nOK\r\n".to_string()),
+                &GatewayEvent::Send("\r\nERROR\r\n".to_string()),
+                &GatewayEvent::Send("\r\nERROR\r\n".to_string()),
+            ]
+        );
+        assert_eq!(gateway.battery(), None);
+    }
+}
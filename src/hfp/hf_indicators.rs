@@ -0,0 +1,41 @@
+/// Assigned numbers of the HFP 1.7 HF indicators.
+pub const ENHANCED_SAFETY: u16 = 1;
+pub const BATTERY_LEVEL: u16 = 2;
+
+/// Indicators listed in `AT+BIND=1,2` or a `+BIND: (1,2)` support response.
+pub fn parse_supported(body: &str) -> Option<Vec<u16>> {
+    let body = body.trim();
+    let body = body
+        .strip_prefix('(')
+        .and_then(|b| b.strip_suffix(')'))
+        .unwrap_or(body);
+    body.split(',').map(|id| id.trim().parse().ok()).collect()
+}
+
+/// `<indicator>,<value>` of `AT+BIEV=` or `+BIEV:`.
+pub fn parse_biev(line: &str) -> Option<(u16, u32)> {
+    let line = line.trim();
+    let body = line
+        .strip_prefix("AT+BIEV=")
+        .or_else(|| line.strip_prefix("+BIEV:"))?;
+    let (id, value) = body.split_once(',')?;
+    Some((id.trim().parse().ok()?, value.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_responses() {
+        assert_eq!(parse_supported("(1,2)"), Some(vec![ENHANCED_SAFETY, BATTERY_LEVEL]));
+        assert_eq!(parse_supported("2"), Some(vec![BATTERY_LEVEL]));
+    }
+
+    #[test]
+    fn test_biev_reports() {
+        assert_eq!(parse_biev("AT+BIEV=2,87"), Some((BATTERY_LEVEL, 87)));
+        assert_eq!(parse_biev("+BIEV: 1,1"), Some((ENHANCED_SAFETY, 1)));
+        assert_eq!(parse_biev("AT+BIEV=2"), None);
+    }
+}
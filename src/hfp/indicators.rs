@@ -0,0 +1,145 @@
+/// Name of the HFP AG indicator carrying the battery charge.
+pub const BATTERY_INDICATOR: &str = "battchg";
+
+/// One indicator announced in the `+CIND=?` test response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indicator {
+    pub name: String,
+    pub min: u32,
+    pub max: u32,
+}
+
+/// The indicators of `+CIND=?` in their announced order. `+CIND:` values
+/// and `+CIEV:` indices refer to this order (1-based for `+CIEV`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndicatorMap {
+    indicators: Vec<Indicator>,
+}
+
+impl IndicatorMap {
+    /// Parses the body of a `+CIND=?` response, e.g.
+    /// `("service",(0,1)),("call",(0,1)),("battchg",(0-5))`.
+    pub fn parse(body: &str) -> Option<Self> {
+        let mut indicators = Vec::new();
+        let mut rest = body;
+
+        while let Some(open) = rest.find('"') {
+            let after_name = &rest[open + 1..];
+            let close = after_name.find('"')?;
+            let name = after_name[..close].to_string();
+
+            let after_name = &after_name[close + 1..];
+            let range_start = after_name.find('(')?;
+            let range_end = after_name[range_start..].find(')')? + range_start;
+            let (min, max) = parse_range(&after_name[range_start + 1..range_end])?;
+
+            indicators.push(Indicator { name, min, max });
+            rest = &after_name[range_end + 1..];
+        }
+
+        if indicators.is_empty() {
+            None
+        } else {
+            Some(Self { indicators })
+        }
+    }
+
+    pub fn indicators(&self) -> &[Indicator] {
+        &self.indicators
+    }
+
+    /// Zero-based position of the battery indicator.
+    pub fn battery_position(&self) -> Option<usize> {
+        self.indicators
+            .iter()
+            .position(|indicator| indicator.name.eq_ignore_ascii_case(BATTERY_INDICATOR))
+    }
+
+    /// Battery percentage from the values of a `+CIND:` read response.
+    pub fn battery_from_values(&self, values: &[u32]) -> Option<u8> {
+        let position = self.battery_position()?;
+        self.scale(position, *values.get(position)?)
+    }
+
+    /// Battery percentage from a `+CIEV: <index>,<value>` report, if it is
+    /// about the battery.
+    pub fn battery_from_event(&self, index: usize, value: u32) -> Option<u8> {
+        let position = self.battery_position()?;
+        if index != position + 1 {
+            return None;
+        }
+        self.scale(position, value)
+    }
+
+    /// Maps a value onto 0-100 using the indicator's announced range, so
+    /// `battchg` 3 of (0-5) is 60%. Computed in `u64`, as the announced
+    /// range can span all of `u32`.
+    fn scale(&self, position: usize, value: u32) -> Option<u8> {
+        let indicator = &self.indicators[position];
+        if indicator.max <= indicator.min {
+            return None;
+        }
+        let value = u64::from(value.clamp(indicator.min, indicator.max) - indicator.min);
+        let range = u64::from(indicator.max - indicator.min);
+        Some((value * 100 / range) as u8)
+    }
+}
+
+/// `0-5` or `0,1` or `0,1,2` -> (min, max)
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let mut values = Vec::new();
+    for part in range.split(',') {
+        match part.split_once('-') {
+            Some((low, high)) => {
+                values.push(low.trim().parse().ok()?);
+                values.push(high.trim().parse().ok()?);
+            }
+            None => values.push(part.trim().parse().ok()?),
+        }
+    }
+    Some((*values.iter().min()?, *values.iter().max()?))
+}
+
+/// Values of a `+CIND:` read response body, e.g. `1,0,0,3`.
+pub fn parse_values(body: &str) -> Option<Vec<u32>> {
+    body.split(',').map(|value| value.trim().parse().ok()).collect()
+}
+
+/// `<index>,<value>` of a `+CIEV:` report.
+pub fn parse_event(body: &str) -> Option<(usize, u32)> {
+    let (index, value) = body.split_once(',')?;
+    Some((index.trim().parse().ok()?, value.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CIND_TEST: &str = r#"("service",(0,1)),("call",(0,1)),("callsetup",(0-3)),("callheld",(0-2)),("signal",(0-5)),("roam",(0,1)),("battchg",(0-5))"#;
+
+    #[test]
+    fn test_parse_indicator_map() {
+        let map = IndicatorMap::parse(CIND_TEST).unwrap();
+        assert_eq!(map.indicators().len(), 7);
+        assert_eq!(map.indicators()[2], Indicator { name: "callsetup".to_string(), min: 0, max: 3 });
+        assert_eq!(map.battery_position(), Some(6));
+        assert!(IndicatorMap::parse("").is_none());
+    }
+
+    #[test]
+    fn test_battery_is_scaled_by_range() {
+        let map = IndicatorMap::parse(CIND_TEST).unwrap();
+        assert_eq!(map.battery_from_values(&[1, 0, 0, 0, 4, 0, 3]), Some(60));
+        assert_eq!(map.battery_from_values(&[1, 0, 0]), None);
+        assert_eq!(map.battery_from_event(7, 5), Some(100));
+        assert_eq!(map.battery_from_event(5, 5), None); // signal
+    }
+
+    #[test]
+    fn test_huge_announced_range() {
+        let map = IndicatorMap::parse(r#"("service",(0,1)),("battchg",(0-4294967295))"#).unwrap();
+        assert_eq!(map.battery_from_values(&[1, u32::MAX]), Some(100));
+        assert_eq!(map.battery_from_event(2, u32::MAX / 2), Some(49));
+        assert_eq!(map.battery_from_event(2, 0), Some(0));
+    }
+}
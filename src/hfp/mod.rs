@@ -0,0 +1,219 @@
+pub mod apple;
+pub mod features;
+pub mod gateway;
+pub mod hf_indicators;
+pub mod indicators;
+pub mod vendor;
+
+use apple::XaplFeatures;
+use features::{AgFeatures, HfFeatures};
+use indicators::IndicatorMap;
+use vendor::VendorRegistry;
+
+/// What a line received from the peer meant for us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HfpEvent {
+    /// Final result of the last command.
+    Ok,
+    Error,
+    /// New battery percentage.
+    Battery(u8),
+}
+
+/// The Hands-Free side of HFP: sends the HF's commands and reads the
+/// gateway's `battchg` indicator out of `+CIND` and `+CIEV`, e.g. a phone's
+/// battery. HF indicators (`AT+BIND`/`AT+BIEV`) only report the HF's own
+/// battery, so they are handled by `gateway::AudioGateway` when we read a
+/// headset instead. Feed it whatever arrives on the socket; it handles lines
+/// split across reads.
+#[derive(Debug, Default)]
+pub struct HfpSession {
+    indicators: Option<IndicatorMap>,
+    battery: Option<u8>,
+    apple_features: Option<XaplFeatures>,
+    ag_features: Option<AgFeatures>,
+    vendors: VendorRegistry,
+    pending: String,
+}
+
+impl HfpSession {
+    /// Features announced in `AT+BRSF`: none, as we never handle calls or
+    /// audio and have no battery of our own to report.
+    pub const HF_FEATURES: HfFeatures = HfFeatures(0);
+
+    /// Exchanges supported features, offers Apple battery reporting, learns
+    /// the indicator layout, reads the current values, then enables `+CIEV`
+    /// reports for later changes. `AT+BRSF` must come first.
+    pub const QUERY_COMMANDS: &'static [&'static str] = &[
+        "AT+BRSF=0",
+        "AT+XAPL=0000-0000-0100,2",
+        "AT+CIND=?",
+        "AT+CIND?",
+        "AT+CMER=3,0,0,1",
+    ];
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A session that also understands the vendor formats of `vendors`.
+    pub fn with_vendors(vendors: VendorRegistry) -> Self {
+        Self {
+            vendors,
+            ..Self::default()
+        }
+    }
+
+    /// Latest battery percentage seen.
+    pub fn battery(&self) -> Option<u8> {
+        self.battery
+    }
+
+    pub fn indicators(&self) -> Option<&IndicatorMap> {
+        self.indicators.as_ref()
+    }
+
+    /// Apple extensions the peer accepted, if it answered `AT+XAPL`.
+    pub fn apple_features(&self) -> Option<XaplFeatures> {
+        self.apple_features
+    }
+
+    pub fn ag_features(&self) -> Option<AgFeatures> {
+        self.ag_features
+    }
+
+    /// Processes received text and returns what the complete lines in it meant.
+    pub fn feed(&mut self, data: &str) -> Vec<HfpEvent> {
+        self.pending.push_str(data);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.pending.find(['\r', '\n']) {
+            let line: String = self.pending.drain(..=end).collect();
+            if let Some(event) = self.handle_line(line.trim()) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn handle_line(&mut self, line: &str) -> Option<HfpEvent> {
+        if line.is_empty() {
+            return None;
+        }
+        if line == "OK" {
+            return Some(HfpEvent::Ok);
+        }
+        if line == "ERROR" || line.starts_with("+CME ERROR") {
+            return Some(HfpEvent::Error);
+        }
+        if let Some((_, features)) = apple::parse_xapl_response(line) {
+            self.apple_features = Some(features);
+            return None;
+        }
+        if let Some(report) = self.vendors.parse(line) {
+            self.battery = Some(report.level);
+            return Some(HfpEvent::Battery(report.level));
+        }
+
+        let (code, body) = line.split_once(':')?;
+        let body = body.trim();
+        let level = match code.trim() {
+            // The test response quotes indicator names; the read response is numbers only
+            "+CIND" if body.contains('"') => {
+                self.indicators = IndicatorMap::parse(body);
+                None
+            }
+            "+CIND" => {
+                let values = indicators::parse_values(body)?;
+                self.indicators.as_ref()?.battery_from_values(&values)
+            }
+            "+CIEV" => {
+                let (index, value) = indicators::parse_event(body)?;
+                self.indicators.as_ref()?.battery_from_event(index, value)
+            }
+            "+BRSF" => {
+                self.ag_features = features::parse_brsf(line).map(AgFeatures);
+                None
+            }
+            _ => None,
+        }?;
+
+        self.battery = Some(level);
+        Some(HfpEvent::Battery(level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Responses of an Android phone to `QUERY_COMMANDS`, which rejects
+    /// `AT+XAPL`, then a battery change.
+    const TRANSCRIPT: &str = "\r\n+BRSF: 871\r\n\r\nOK\r\n\
+        \r\nERROR\r\n\
+        \r\n+CIND: (\"call\",(0,1)),(\"callsetup\",(0-3)),(\"service\",(0-1)),\
+        (\"signal\",(0-5)),(\"roam\",(0,1)),(\"battchg\",(0-5)),(\"callheld\",(0-2))\r\n\r\nOK\r\n\
+        \r\n+CIND: 0,0,1,4,0,4,0\r\n\r\nOK\r\n\
+        \r\nOK\r\n\
+        \r\n+CIEV: 6,2\r\n";
+
+    #[test]
+    fn test_transcript_yields_scaled_battery() {
+        let mut session = HfpSession::new();
+        let events = session.feed(TRANSCRIPT);
+
+        assert_eq!(
+            events,
+            vec![
+                HfpEvent::Ok,
+                HfpEvent::Error,
+                HfpEvent::Ok,
+                HfpEvent::Battery(80),
+                HfpEvent::Ok,
+                HfpEvent::Ok,
+                HfpEvent::Battery(40),
+            ]
+        );
+        assert_eq!(session.battery(), Some(40));
+        assert!(session.ag_features().unwrap().contains(AgFeatures::REJECT_CALL));
+        assert_eq!(session.apple_features(), None);
+    }
+
+    #[test]
+    fn test_lines_split_across_reads() {
+        let mut session = HfpSession::new();
+        let (first, second) = TRANSCRIPT.split_at(40);
+
+        let mut events = session.feed(first);
+        events.extend(session.feed(second));
+        assert_eq!(events.iter().filter(|e| matches!(e, HfpEvent::Battery(_))).count(), 2);
+    }
+
+    #[test]
+    fn test_no_hf_indicators_are_offered() {
+        assert_eq!(HfpSession::QUERY_COMMANDS[0], format!("AT+BRSF={}", HfpSession::HF_FEATURES.0));
+        assert!(!HfpSession::HF_FEATURES.contains(HfFeatures::HF_INDICATORS));
+        assert!(!HfpSession::QUERY_COMMANDS.iter().any(|command| command.starts_with("AT+BIND")));
+    }
+
+    #[test]
+    fn test_apple_battery_reports() {
+        let mut session = HfpSession::new();
+        let events = session.feed("\r\n+XAPL=iPhone,6\r\n\r\nOK\r\n+IPHONEACCEV: 2,2,0,1,3\r\n");
+
+        assert_eq!(events, vec![HfpEvent::Ok, HfpEvent::Battery(40)]);
+        assert!(session
+            .apple_features()
+            .unwrap()
+            .contains(XaplFeatures::BATTERY_REPORTING));
+    }
+
+    #[test]
+    fn test_values_without_indicator_map_are_ignored() {
+        // Indicator 1 is "service" on most headsets; without +CIND=? there is
+        // no way to tell which value is the battery.
+        let mut session = HfpSession::new();
+        assert!(session.feed("+CIND: 1,0,0,4,0,5\r\n").is_empty());
+        assert_eq!(session.battery(), None);
+    }
+}
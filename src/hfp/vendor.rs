@@ -0,0 +1,159 @@
+use std::fmt;
+
+use super::apple::AccessoryEvent;
+
+/// Reads a battery level out of one vendor-specific AT command or result
+/// code. Lines of other vendors must return `None`.
+pub trait VendorParser: Send + Sync {
+    fn vendor(&self) -> &'static str;
+
+    fn parse(&self, line: &str) -> Option<u8>;
+}
+
+/// A battery level and the vendor extension that reported it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VendorReport {
+    pub vendor: &'static str,
+    pub level: u8,
+}
+
+/// Vendor parsers tried in order until one recognizes the line.
+pub struct VendorRegistry {
+    parsers: Vec<Box<dyn VendorParser>>,
+}
+
+impl VendorRegistry {
+    pub fn new() -> Self {
+        Self { parsers: Vec::new() }
+    }
+
+    /// Every vendor format we know the layout of.
+    pub fn builtin() -> Self {
+        Self::new().with_parser(AppleParser).with_parser(XeventParser)
+    }
+
+    pub fn with_parser(mut self, parser: impl VendorParser + 'static) -> Self {
+        self.parsers.push(Box::new(parser));
+        self
+    }
+
+    pub fn vendors(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.parsers.iter().map(|parser| parser.vendor())
+    }
+
+    pub fn parse(&self, line: &str) -> Option<VendorReport> {
+        let line = line.trim();
+        self.parsers.iter().find_map(|parser| {
+            parser.parse(line).map(|level| VendorReport {
+                vendor: parser.vendor(),
+                level,
+            })
+        })
+    }
+}
+
+impl Default for VendorRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl fmt::Debug for VendorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.vendors()).finish()
+    }
+}
+
+/// Apple `+IPHONEACCEV` key/value reports.
+pub struct AppleParser;
+
+impl VendorParser for AppleParser {
+    fn vendor(&self) -> &'static str {
+        "Apple"
+    }
+
+    fn parse(&self, line: &str) -> Option<u8> {
+        AccessoryEvent::parse(line)?.battery
+    }
+}
+
+/// Plantronics `+XEVENT=BATTERY,<level>,<number of levels>,<minutes of talk
+/// time>,<charging>`, with `level` counted from 0 to `number of levels - 1`.
+/// The trailing fields are optional.
+pub struct XeventParser;
+
+impl VendorParser for XeventParser {
+    fn vendor(&self) -> &'static str {
+        "Plantronics"
+    }
+
+    fn parse(&self, line: &str) -> Option<u8> {
+        let body = line
+            .strip_prefix("AT+XEVENT=")
+            .or_else(|| line.strip_prefix("+XEVENT:"))
+            .or_else(|| line.strip_prefix("+XEVENT="))?;
+        let mut fields = body.split(',').map(str::trim);
+        if !fields.next()?.eq_ignore_ascii_case("BATTERY") {
+            return None;
+        }
+
+        let level: u64 = fields.next()?.parse().ok()?;
+        let levels: u64 = fields.next()?.parse().ok()?;
+        if levels < 2 || level >= levels {
+            return None;
+        }
+        u8::try_from(level.checked_mul(100)? / (levels - 1)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines seen from headsets, and the level each should give.
+    const FIXTURES: &[(&str, Option<(&str, u8)>)] = &[
+        ("AT+XEVENT=BATTERY,6,11,461,0", Some(("Plantronics", 60))),
+        ("+XEVENT: BATTERY,4,5,120,1", Some(("Plantronics", 100))),
+        ("AT+XEVENT=BATTERY,0,5", Some(("Plantronics", 0))),
+        ("AT+XEVENT=USER-AGENT,Plantronics,0x1A2B", None),
+        ("AT+XEVENT=BATTERY,3,1", None),
+        ("AT+XEVENT=BATTERY,5,5,0,0", None),
+        ("AT+XEVENT=BATTERY,4294967295,4294967296", Some(("Plantronics", 100))),
+        ("AT+XEVENT=BATTERY,200000000000000000,200000000000000001", None),
+        ("AT+IPHONEACCEV=2,1,7,2,0", Some(("Apple", 80))),
+        ("+IPHONEACCEV: 1,2,1", None),
+        ("AT+BIEV=2,50", None),
+    ];
+
+    #[test]
+    fn test_builtin_parsers_match_fixtures() {
+        let registry = VendorRegistry::builtin();
+        for (line, expected) in FIXTURES {
+            let report = registry.parse(line).map(|r| (r.vendor, r.level));
+            assert_eq!(report, *expected, "{}", line);
+        }
+    }
+
+    struct Percent;
+
+    impl VendorParser for Percent {
+        fn vendor(&self) -> &'static str {
+            "Test"
+        }
+
+        fn parse(&self, line: &str) -> Option<u8> {
+            line.strip_prefix("+TESTBATT:")?.trim().parse().ok()
+        }
+    }
+
+    #[test]
+    fn test_registry_accepts_new_parsers() {
+        let registry = VendorRegistry::builtin().with_parser(Percent);
+        assert_eq!(registry.vendors().collect::<Vec<_>>(), ["Apple", "Plantronics", "Test"]);
+        assert_eq!(
+            registry.parse("+TESTBATT: 42"),
+            Some(VendorReport { vendor: "Test", level: 42 })
+        );
+        assert!(VendorRegistry::new().parse("AT+XEVENT=BATTERY,6,11").is_none());
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::battery_history::{BatteryHistory, BatterySample};
+
+/// Bump when the on-disk layout changes and add a step to `migrate`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+pub(crate) const APP_DIR_NAME: &str = "bt-battery-estimator";
+const HISTORY_FILE_NAME: &str = "history.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredHistory {
+    version: u32,
+    devices: HashMap<String, StoredDevice>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredDevice {
+    samples: Vec<BatterySample>,
+}
+
+/// JSON file holding the battery history of every known device.
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Store in the per-user data directory, if one can be determined.
+    pub fn open_default() -> Option<Self> {
+        data_dir().map(|dir| Self::new(dir.join(HISTORY_FILE_NAME)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads all histories. A missing file is an empty history; a file that
+    /// cannot be read is moved aside so the next save does not destroy it.
+    pub fn load(&self) -> Result<HashMap<String, BatteryHistory>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        };
+
+        let stored = match serde_json::from_str(&contents)
+            .map_err(anyhow::Error::from)
+            .and_then(migrate)
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                let backup = self.path.with_extension("json.bak");
+                let _ = fs::rename(&self.path, &backup);
+                return Err(e.context(format!(
+                    "Unreadable history file moved to {}",
+                    backup.display()
+                )));
+            }
+        };
+
+        Ok(stored
+            .devices
+            .into_iter()
+            .map(|(address, device)| {
+                let mut history = BatteryHistory::new();
+                for sample in device.samples {
+                    history.record(sample);
+                }
+                (address, history)
+            })
+            .collect())
+    }
+
+    /// Writes all histories atomically.
+    pub fn save(&self, histories: &HashMap<String, BatteryHistory>) -> Result<()> {
+        let stored = StoredHistory {
+            version: SCHEMA_VERSION,
+            devices: histories
+                .iter()
+                .map(|(address, history)| {
+                    let device = StoredDevice {
+                        samples: history.samples().to_vec(),
+                    };
+                    (address.clone(), device)
+                })
+                .collect(),
+        };
+        write_atomically(&self.path, &serde_json::to_vec(&stored)?)
+    }
+}
+
+/// Writes `contents` to a temporary file that is flushed to disk and then
+/// renamed over `path`, so readers never see a partial file. The temporary
+/// name is unique per process and call, so concurrent writers (the GUI and
+/// the daemon share the store) never write into the same file.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    static NEXT_TMP_ID: AtomicU64 = AtomicU64::new(0);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file path", path.display()))?;
+    let tmp_path = path.with_file_name(format!(
+        "{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        NEXT_TMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = File::create(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| fs::rename(&tmp_path, path).with_context(|| format!("Failed to replace {}", path.display())));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    written
+}
+
+/// Brings a stored document of any known version up to `SCHEMA_VERSION`.
+fn migrate(value: serde_json::Value) -> Result<StoredHistory> {
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| anyhow!("History file has no schema version"))?;
+
+    match version {
+        // Version 1 samples have no component; they were all overall levels
+        1 | 2 => {
+            let mut stored: StoredHistory = serde_json::from_value(value)?;
+            stored.version = SCHEMA_VERSION;
+            Ok(stored)
+        }
+        v => Err(anyhow!(
+            "History schema version {} is not supported (expected at most {})",
+            v,
+            SCHEMA_VERSION
+        )),
+    }
+}
+
+/// Per-user application data directory.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.map(|dir| dir.join(APP_DIR_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery_history::{BatteryBackend, ConnectionState};
+    use crate::bluetooth_battery::BatteryComponent;
+
+    fn temp_store(name: &str) -> HistoryStore {
+        let dir = std::env::temp_dir().join(format!("bt-battery-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        HistoryStore::new(dir.join(HISTORY_FILE_NAME))
+    }
+
+    fn sample(timestamp: u64, level: u8) -> BatterySample {
+        BatterySample {
+            timestamp,
+            level,
+            source: BatteryBackend::Rfcomm,
+            connection: ConnectionState::Connected,
+            component: BatteryComponent::Overall,
+        }
+    }
+
+    #[test]
+    fn test_missing_file_loads_empty() {
+        let store = temp_store("missing");
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let store = temp_store("round-trip");
+        let mut history = BatteryHistory::new();
+        history.record(sample(1_000, 90));
+        history.record(sample(2_000, 85));
+
+        let mut histories = HashMap::new();
+        histories.insert("00:11:22:33:44:55".to_string(), history);
+        store.save(&histories).unwrap();
+
+        let leftovers: Vec<_> = fs::read_dir(store.path().parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(leftovers, [HISTORY_FILE_NAME]);
+
+        let loaded = store.load().unwrap();
+        let samples = loaded["00:11:22:33:44:55"].samples();
+        assert_eq!(samples, &[sample(1_000, 90), sample(2_000, 85)]);
+    }
+
+    #[test]
+    fn test_concurrent_saves_leave_a_readable_file() {
+        let store = std::sync::Arc::new(temp_store("concurrent"));
+        let writers: Vec<_> = (0..8u64)
+            .map(|writer| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        let mut history = BatteryHistory::new();
+                        for t in 0..50 {
+                            history.record(sample(writer * 1_000_000 + i * 1_000 + t, 80));
+                        }
+                        let mut histories = HashMap::new();
+                        histories.insert(format!("device-{}", writer), history);
+                        store.save(&histories).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(store.load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_version_1_samples_become_overall() {
+        let store = temp_store("version-1");
+        fs::create_dir_all(store.path().parent().unwrap()).unwrap();
+        fs::write(
+            store.path(),
+            r#"{"version": 1, "devices": {"00:11:22:33:44:55": {"samples": [
+                {"timestamp": 1000, "level": 90, "source": "Uwp", "connection": "Connected"}
+            ]}}}"#,
+        )
+        .unwrap();
+
+        let loaded = store.load().unwrap();
+        let samples = loaded["00:11:22:33:44:55"].samples();
+        assert_eq!(samples[0].component, BatteryComponent::Overall);
+        assert_eq!(samples[0].level, 90);
+    }
+
+    #[test]
+    fn test_unsupported_version_is_moved_aside() {
+        let store = temp_store("future-version");
+        fs::create_dir_all(store.path().parent().unwrap()).unwrap();
+        fs::write(store.path(), r#"{"version": 99, "devices": {}}"#).unwrap();
+
+        let err = store.load().unwrap_err();
+        assert!(format!("{:#}", err).contains("version 99"));
+        assert!(!store.path().exists());
+        assert!(store.path().with_extension("json.bak").exists());
+    }
+}
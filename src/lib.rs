@@ -0,0 +1,34 @@
+pub mod address;
+pub mod at_commands;
+pub mod battery_history;
+pub mod bluetooth_battery;
+pub mod config;
+pub mod device_registry;
+pub mod device_type;
+pub mod devices;
+pub mod estimator;
+pub mod filter;
+pub mod hfp;
+pub mod history_store;
+pub mod monitor;
+pub mod poller;
+pub mod report;
+pub mod rfcomm_io;
+pub mod rfcomm_profile;
+pub mod sources;
+#[cfg(windows)]
+pub mod uwp_bluetooth;
+#[cfg(windows)]
+pub mod windows_rfcomm;
+
+pub use address::BluetoothAddress;
+pub use battery_history::{BatteryBackend, BatteryHistory, BatterySample, ConnectionState};
+pub use bluetooth_battery::BatteryResult;
+pub use config::{Config, ConfigWatcher};
+pub use device_registry::{DeviceRegistry, DeviceSettings};
+pub use device_type::DeviceType;
+pub use devices::BluetoothDevice;
+pub use estimator::{Accuracy, Estimate};
+pub use filter::{DeviceFilter, FilterRule};
+pub use monitor::BatteryMonitor;
+pub use poller::{PollConfig, Poller};
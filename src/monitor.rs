@@ -0,0 +1,603 @@
+use std::collections::{HashMap, HashSet};
+use futures::future::join_all;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::battery_history::{BatteryHistory, BatterySample};
+use crate::bluetooth_battery::{BatteryComponent, BatteryResult};
+use crate::config::Config;
+use crate::device_registry::{DeviceRegistry, DeviceSettings};
+use crate::device_type::DeviceType;
+use crate::devices::{BluetoothDevice, ComponentStatus};
+use crate::estimator::rated_estimate;
+use crate::filter::DeviceFilter;
+use crate::history_store::HistoryStore;
+use crate::sources::{self, BatteryReading, BatteryUpdate, DiscoveredDevice, SourceChain};
+
+/// How long changes to the history may wait before they are written, so
+/// polls and pushed updates coming in together share one save.
+pub const SAVE_DELAY: Duration = Duration::from_secs(30);
+
+/// Ties the backends, the battery history and its on-disk store together.
+/// Front ends create one, call `refresh`, and `flush_history` before exiting.
+pub struct BatteryMonitor {
+    chain: SourceChain,
+    saver: Option<Arc<HistorySaver>>,
+    history: Arc<Mutex<HashMap<String, BatteryHistory>>>,
+    devices: Mutex<Vec<BluetoothDevice>>,
+    updates: Mutex<Option<UnboundedSender<BatteryUpdate>>>,
+    subscribed: Mutex<HashSet<String>>,
+    filter: Mutex<DeviceFilter>,
+    registry: Mutex<DeviceRegistry>,
+    rated_runtimes: Mutex<HashMap<DeviceType, Duration>>,
+}
+
+impl BatteryMonitor {
+    /// Loads any existing history from `store` before the first refresh.
+    pub fn new(chain: SourceChain, store: Option<HistoryStore>) -> Self {
+        let history = match &store {
+            Some(store) => store.load().unwrap_or_else(|e| {
+                eprintln!("Failed to load battery history: {:#}", e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+
+        let history = Arc::new(Mutex::new(history));
+        Self {
+            chain,
+            saver: store.map(|store| {
+                Arc::new(HistorySaver {
+                    store,
+                    history: history.clone(),
+                    dirty: AtomicBool::new(false),
+                    scheduled: AtomicBool::new(false),
+                    writing: Mutex::new(()),
+                })
+            }),
+            history,
+            devices: Mutex::new(Vec::new()),
+            updates: Mutex::new(None),
+            subscribed: Mutex::new(HashSet::new()),
+            filter: Mutex::new(DeviceFilter::default()),
+            registry: Mutex::new(DeviceRegistry::new()),
+            rated_runtimes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The configured backends with the default history file. Simulated runs
+    /// keep their samples in memory so they do not mix with the real history.
+    pub fn configured() -> Self {
+        let store = if sources::scenario_path().is_some() {
+            None
+        } else {
+            HistoryStore::open_default()
+        };
+        let monitor = Self::new(SourceChain::configured(), store);
+        if let Some(path) = DeviceRegistry::default_path() {
+            match DeviceRegistry::load(&path) {
+                Ok(registry) => monitor.set_registry(registry),
+                Err(e) => eprintln!("Failed to load device registry: {:#}", e),
+            }
+        }
+        monitor
+    }
+
+    /// Which devices later refreshes show.
+    pub fn set_filter(&self, filter: DeviceFilter) {
+        *self.filter.lock().unwrap() = filter;
+    }
+
+    pub fn filter(&self) -> DeviceFilter {
+        self.filter.lock().unwrap().clone()
+    }
+
+    /// Applies the filter, estimate table, backend order and backend
+    /// settings of a new or reloaded configuration. Polling intervals belong
+    /// to the `Poller`.
+    pub fn apply_config(&self, config: &Config) {
+        self.set_filter(config.filter.clone());
+        *self.rated_runtimes.lock().unwrap() = config.rated_runtimes();
+        self.chain.set_priority(&config.source_priority());
+        self.chain.configure(config);
+    }
+
+    /// Per-device settings later refreshes and updates apply.
+    pub fn set_registry(&self, registry: DeviceRegistry) {
+        *self.registry.lock().unwrap() = registry;
+    }
+
+    pub fn registry(&self) -> DeviceRegistry {
+        self.registry.lock().unwrap().clone()
+    }
+
+    /// Backends that can notify will push battery changes to `updates`; pass
+    /// each one to `apply_update`.
+    pub fn enable_updates(&self, updates: UnboundedSender<BatteryUpdate>) {
+        *self.updates.lock().unwrap() = Some(updates);
+    }
+
+    /// Discovers devices, reads their battery and records the readings.
+    pub async fn refresh(&self) -> Vec<BluetoothDevice> {
+        self.refresh_where(|_| true).await
+    }
+
+    /// Discovers devices but only reads the battery of those `due` selects
+    /// (by address). Others keep their last reading; new devices are always
+    /// read. Reads run concurrently, so a slow device does not hold up the rest.
+    pub async fn refresh_where(&self, due: impl Fn(&str) -> bool) -> Vec<BluetoothDevice> {
+        let previous = self.devices();
+        let filter = self.filter();
+        let registry = self.registry();
+        // In discovery order; `None` until the battery is read
+        let mut slots: Vec<Option<BluetoothDevice>> = Vec::new();
+        let mut to_read = Vec::new();
+
+        for discovered in self.chain.discover().await {
+            let settings = registry.get(&discovered.mac_address);
+            let device = BluetoothDevice {
+                name: settings.alias.clone().unwrap_or_else(|| discovered.name.clone()),
+                mac_address: discovered.mac_address.clone(),
+                device_type: settings.device_type.unwrap_or_else(|| discovered.device_type()),
+                battery_level: None,
+                battery: BatteryResult::new(),
+                battery_estimate: "N/A".to_string(),
+                remaining_minutes: None,
+                accuracy: "N/A".to_string(),
+                low_battery_threshold: settings.low_battery_threshold,
+                components: Vec::new(),
+                profile: None,
+                icon: settings.icon.clone(),
+            };
+            if settings.hidden || !filter.admits(&device.name, &device.mac_address, device.device_type) {
+                continue;
+            }
+
+            if !due(&discovered.mac_address) {
+                if let Some(known) = previous.iter().find(|d| d.mac_address == discovered.mac_address) {
+                    // Keep the reading but pick up registry changes
+                    slots.push(Some(BluetoothDevice {
+                        name: device.name,
+                        device_type: device.device_type,
+                        low_battery_threshold: device.low_battery_threshold,
+                        icon: device.icon,
+                        ..known.clone()
+                    }));
+                    continue;
+                }
+            }
+            to_read.push((slots.len(), discovered, device, settings));
+            slots.push(None);
+        }
+
+        let readings = join_all(
+            to_read
+                .iter()
+                .map(|(_, discovered, _, settings)| self.chain.read_battery_preferring(discovered, settings.preferred_backend)),
+        )
+        .await;
+
+        for ((slot, discovered, mut device, settings), reading) in to_read.into_iter().zip(readings) {
+            if let Some(reading) = reading {
+                device.battery = reading.battery.clone();
+                device.battery_level = reading.battery.get_primary_level();
+                device.profile = reading.profile;
+                let rated_runtime = self.rated_runtime(&settings, device.device_type);
+                self.apply_battery_estimate(&mut device, &reading, rated_runtime);
+            }
+
+            self.subscribe_once(&discovered).await;
+            slots[slot] = Some(device);
+        }
+
+        let devices: Vec<BluetoothDevice> = slots.into_iter().flatten().filter(|d| filter.shows(d)).collect();
+        self.save_history();
+        *self.devices.lock().unwrap() = devices.clone();
+        devices
+    }
+
+    /// Records a pushed battery change and returns the updated device list.
+    pub fn apply_update(&self, update: &BatteryUpdate) -> Vec<BluetoothDevice> {
+        let settings = self.registry.lock().unwrap().get(&update.mac_address);
+        let devices = {
+            let mut devices = self.devices.lock().unwrap();
+            for device in devices.iter_mut().filter(|d| d.mac_address == update.mac_address) {
+                device.battery = update.reading.battery.clone();
+                device.battery_level = update.reading.battery.get_primary_level();
+                device.profile = update.reading.profile;
+                let rated_runtime = self.rated_runtime(&settings, device.device_type);
+                self.apply_battery_estimate(device, &update.reading, rated_runtime);
+            }
+            devices.clone()
+        };
+        self.save_history();
+        devices
+    }
+
+    /// Devices as of the last refresh or update.
+    pub fn devices(&self) -> Vec<BluetoothDevice> {
+        self.devices.lock().unwrap().clone()
+    }
+
+    /// Whether a backend pushes battery changes for this device, so it does
+    /// not need to be polled.
+    pub fn is_subscribed(&self, mac_address: &str) -> bool {
+        self.subscribed.lock().unwrap().contains(mac_address)
+    }
+
+    pub fn history(&self, mac_address: &str) -> Option<BatteryHistory> {
+        self.history.lock().unwrap().get(mac_address).cloned()
+    }
+
+    /// The device's own rated runtime, or else the configured one for its type.
+    fn rated_runtime(&self, settings: &DeviceSettings, device_type: DeviceType) -> Option<Duration> {
+        settings
+            .rated_runtime()
+            .or_else(|| self.rated_runtimes.lock().unwrap().get(&device_type).copied())
+    }
+
+    /// Asks the backends to push battery changes for a device we have not
+    /// subscribed to yet.
+    async fn subscribe_once(&self, discovered: &DiscoveredDevice) {
+        let Some(updates) = self.updates.lock().unwrap().clone() else {
+            return;
+        };
+        if !self.subscribed.lock().unwrap().insert(discovered.mac_address.clone()) {
+            return;
+        }
+        if !self.chain.subscribe(discovered, updates).await {
+            self.subscribed.lock().unwrap().remove(&discovered.mac_address);
+        }
+    }
+
+    /// Records every reported component and estimates each one. The device
+    /// estimate is the overall one, or else that of the earbud that runs out
+    /// first. Without a measured rate, `rated_runtime` replaces the built-in
+    /// table for the device and its earbuds.
+    fn apply_battery_estimate(
+        &self,
+        device: &mut BluetoothDevice,
+        reading: &BatteryReading,
+        rated_runtime: Option<Duration>,
+    ) {
+        let mut history = self.history.lock().unwrap();
+        let device_history = history.entry(device.mac_address.clone()).or_default();
+
+        let mut estimates = Vec::new();
+        for (component, level) in reading.battery.components() {
+            device_history.record(BatterySample {
+                timestamp: reading.observed_at,
+                level,
+                source: reading.source,
+                connection: reading.connection,
+                component,
+            });
+            let mut estimate = device_history.estimate_component(component, level);
+            if let Some(rated_runtime) = rated_runtime {
+                if estimate.drain_per_hour.is_none() && component != BatteryComponent::Case {
+                    estimate = rated_estimate(rated_runtime, level);
+                }
+            }
+            estimates.push((component, level, estimate));
+        }
+
+        device.components = estimates
+            .iter()
+            .filter(|(component, _, _)| *component != BatteryComponent::Overall)
+            .map(|(component, level, estimate)| ComponentStatus::new(*component, *level, estimate))
+            .collect();
+
+        let estimate = estimates
+            .iter()
+            .find(|(component, _, _)| *component == BatteryComponent::Overall)
+            .or_else(|| {
+                estimates
+                    .iter()
+                    .filter(|(component, _, _)| matches!(component, BatteryComponent::Left | BatteryComponent::Right))
+                    .min_by_key(|(_, _, estimate)| estimate.remaining)
+            })
+            .or_else(|| estimates.first());
+        if let Some((_, _, estimate)) = estimate {
+            device.accuracy = estimate.accuracy.label().to_string();
+            device.battery_estimate = estimate.format_remaining();
+            device.remaining_minutes = Some(estimate.remaining.as_secs() / 60);
+        }
+    }
+
+    /// Marks the history changed; it is written within `SAVE_DELAY`.
+    fn save_history(&self) {
+        if let Some(saver) = &self.saver {
+            saver.request();
+        }
+    }
+
+    /// Writes any history changes that are still waiting for `SAVE_DELAY`.
+    pub async fn flush_history(&self) {
+        if let Some(saver) = self.saver.clone() {
+            let _ = tokio::task::spawn_blocking(move || saver.save_if_dirty()).await;
+        }
+    }
+}
+
+/// Writes the history to its store off the async worker threads, at most
+/// once per `SAVE_DELAY`.
+struct HistorySaver {
+    store: HistoryStore,
+    history: Arc<Mutex<HashMap<String, BatteryHistory>>>,
+    dirty: AtomicBool,
+    scheduled: AtomicBool,
+    /// Keeps two saves of this process from overlapping.
+    writing: Mutex<()>,
+}
+
+impl HistorySaver {
+    fn request(self: &Arc<Self>) {
+        self.dirty.store(true, Ordering::SeqCst);
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            self.save_if_dirty();
+            return;
+        };
+        if self.scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let saver = self.clone();
+        runtime.spawn(async move {
+            tokio::time::sleep(SAVE_DELAY).await;
+            // Changes made during the write schedule the next one
+            saver.scheduled.store(false, Ordering::SeqCst);
+            let _ = tokio::task::spawn_blocking(move || saver.save_if_dirty()).await;
+        });
+    }
+
+    /// Saves the history, first picking up samples other processes (the GUI
+    /// and the daemon) have written to the same store since we loaded it.
+    /// Blocks on file I/O; the history is only locked for the merge.
+    fn save_if_dirty(&self) {
+        let _writing = self.writing.lock().unwrap();
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return;
+        }
+
+        let stored = self.store.load();
+        let snapshot = {
+            let mut history = self.history.lock().unwrap();
+            match stored {
+                Ok(stored) => {
+                    for (address, stored_history) in stored {
+                        history.entry(address).or_default().merge(&stored_history);
+                    }
+                }
+                Err(e) => eprintln!("Failed to reload battery history: {:#}", e),
+            }
+            history.clone()
+        };
+        if let Err(e) = self.store.save(&snapshot) {
+            eprintln!("Failed to save battery history: {:#}", e);
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery_history::{BatteryBackend, ConnectionState};
+    use crate::sources::mock::{MockSource, Scenario};
+    use crate::sources::{BatterySource, SourceCapabilities};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    /// Simulated devices that only report battery when read, each read
+    /// taking `delay`.
+    struct SlowSource {
+        inner: MockSource,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl BatterySource for SlowSource {
+        fn backend(&self) -> BatteryBackend {
+            self.inner.backend()
+        }
+
+        fn capabilities(&self) -> SourceCapabilities {
+            self.inner.capabilities()
+        }
+
+        async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+            let mut devices = self.inner.discover().await?;
+            devices.iter_mut().for_each(|device| device.battery = None);
+            Ok(devices)
+        }
+
+        async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.read_battery(device).await
+        }
+    }
+
+    fn monitor() -> BatteryMonitor {
+        let scenario = Scenario::from_json(
+            r#"{
+                "devices": [
+                    { "name": "Sim Earphones", "mac_address": "02:00:00:00:00:01", "start_level": 80 },
+                    { "name": "Sim Thing", "mac_address": "02:00:00:00:00:02", "start_level": 50 },
+                    { "name": "Sim Tracker", "mac_address": "02:00:00:00:00:03", "start_level": 0, "reports_battery": false }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let chain = SourceChain::new().with_source(MockSource::with_manual_clock(scenario, 1_700_000_000));
+        BatteryMonitor::new(chain, None)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_records_history() {
+        let monitor = monitor();
+        let devices = monitor.refresh().await;
+
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].device_type, DeviceType::Earbuds);
+        assert_eq!(devices[0].battery_level, Some(80));
+        assert_eq!(devices[0].accuracy, "Measuring");
+        assert_eq!(monitor.history("02:00:00:00:00:01").unwrap().samples().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_filter_hides_devices() {
+        let monitor = monitor();
+        // Unclassified devices are shown, with or without a level
+        assert_eq!(monitor.refresh().await[1].device_type, DeviceType::Other);
+
+        monitor.set_filter(DeviceFilter {
+            allow: Vec::new(),
+            deny: vec!["type:earbuds".parse().unwrap()],
+            only_with_battery: true,
+        });
+        let devices = monitor.refresh().await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "Sim Thing");
+    }
+
+    #[tokio::test]
+    async fn test_registry_overrides_discovery_and_estimate() {
+        let monitor = monitor();
+        let mut registry = DeviceRegistry::new();
+        let settings = DeviceSettings {
+            alias: Some("Desk Headset".to_string()),
+            device_type: Some(DeviceType::Headset),
+            rated_runtime_hours: Some(30.0),
+            low_battery_threshold: Some(90),
+            icon: Some("headset.png".into()),
+            ..Default::default()
+        };
+        registry.set("02:00:00:00:00:01".parse().unwrap(), settings).unwrap();
+        let hidden = DeviceSettings {
+            hidden: true,
+            ..Default::default()
+        };
+        registry.set("02:00:00:00:00:03".parse().unwrap(), hidden).unwrap();
+        monitor.set_registry(registry);
+
+        let devices = monitor.refresh().await;
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "Desk Headset");
+        assert_eq!(devices[0].device_type, DeviceType::Headset);
+        assert_eq!(devices[0].icon.as_deref(), Some(std::path::Path::new("headset.png")));
+        // 80% of the rated 30h while there is no measured rate
+        assert_eq!(devices[0].battery_estimate, "1d 0h");
+        assert!(devices[0].is_low_battery(20));
+        assert!(!devices[1].is_low_battery(20));
+    }
+
+    #[tokio::test]
+    async fn test_apply_update_changes_known_device() {
+        let monitor = monitor();
+        monitor.refresh().await;
+
+        let devices = monitor.apply_update(&BatteryUpdate {
+            mac_address: "02:00:00:00:00:01".to_string(),
+            reading: BatteryReading {
+                battery: BatteryResult {
+                    overall: Some(75),
+                    left: None,
+                    right: None,
+                    case: None,
+                },
+                source: BatteryBackend::Mock,
+                connection: ConnectionState::Connected,
+                observed_at: 1_700_000_600,
+                profile: None,
+            },
+        });
+
+        assert_eq!(devices[0].battery_level, Some(75));
+        assert_eq!(monitor.devices()[0].battery_level, Some(75));
+        assert_eq!(monitor.history("02:00:00:00:00:01").unwrap().samples().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_components_are_tracked_separately() {
+        let scenario = Scenario::from_json(
+            r#"{
+                "devices": [{
+                    "name": "Sim Earphones", "mac_address": "02:00:00:00:00:01", "start_level": 100,
+                    "components": [
+                        { "component": "Left", "start_level": 90, "phases": [{ "duration_mins": 600, "rate_per_hour": -10 }] },
+                        { "component": "Right", "start_level": 60, "phases": [{ "duration_mins": 600, "rate_per_hour": -20 }] },
+                        { "component": "Case", "start_level": 100 }
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let source = Arc::new(MockSource::with_manual_clock(scenario, 1_700_000_000));
+        let chain = SourceChain::new().with_source(source.clone());
+        let monitor = BatteryMonitor::new(chain, None);
+
+        for _ in 0..=4 {
+            monitor.refresh().await;
+            source.advance(Duration::from_secs(15 * 60));
+        }
+
+        let device = &monitor.devices()[0];
+        let right = device.component(BatteryComponent::Right).unwrap();
+        assert_eq!(device.components.len(), 3);
+        assert_eq!(device.component(BatteryComponent::Left).unwrap().level, 80);
+        assert_eq!(right.level, 40);
+        assert_eq!(right.battery_estimate, "2h 0m");
+        // The right earbud runs out first
+        assert_eq!(device.battery_estimate, right.battery_estimate);
+
+        let samples = monitor.history("02:00:00:00:00:01").unwrap().samples().len();
+        assert_eq!(samples, 15);
+    }
+
+    #[tokio::test]
+    async fn test_history_saves_are_batched() {
+        let dir = std::env::temp_dir().join(format!("bt-battery-monitor-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("history.json");
+        let monitor = BatteryMonitor::new(monitor().chain, Some(HistoryStore::new(path.clone())));
+
+        monitor.refresh().await;
+        monitor.refresh().await;
+        // Written after SAVE_DELAY, not on every refresh
+        assert!(!path.exists());
+
+        monitor.flush_history().await;
+        let stored = HistoryStore::new(path).load().unwrap();
+        assert_eq!(stored["02:00:00:00:00:01"].samples().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_devices_are_read_concurrently() {
+        let scenario = Scenario::from_json(
+            r#"{
+                "devices": [
+                    { "name": "Sim Earphones A", "mac_address": "02:00:00:00:00:01", "start_level": 80 },
+                    { "name": "Sim Earphones B", "mac_address": "02:00:00:00:00:02", "start_level": 70 },
+                    { "name": "Sim Earphones C", "mac_address": "02:00:00:00:00:03", "start_level": 60 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let source = SlowSource {
+            inner: MockSource::with_manual_clock(scenario, 1_700_000_000),
+            delay: Duration::from_millis(300),
+        };
+        let monitor = BatteryMonitor::new(SourceChain::new().with_source(source), None);
+
+        let started = Instant::now();
+        let devices = monitor.refresh().await;
+        assert!(started.elapsed() < Duration::from_millis(800));
+
+        let levels: Vec<Option<u8>> = devices.iter().map(|d| d.battery_level).collect();
+        assert_eq!(levels, [Some(80), Some(70), Some(60)]);
+    }
+}
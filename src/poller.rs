@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::devices::BluetoothDevice;
+use crate::monitor::BatteryMonitor;
+
+/// Bounds and thresholds of the adaptive polling schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollConfig {
+    /// Used while a level is changing or low.
+    pub min_interval: Duration,
+    /// Upper bound the interval backs off to while a level is stable.
+    pub max_interval: Duration,
+    /// At or below this level a device is always polled at `min_interval`.
+    pub low_level: u8,
+    /// How often discovery runs even if no device is due, to pick up new ones.
+    pub discovery_interval: Duration,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_secs(60),
+            max_interval: Duration::from_secs(15 * 60),
+            low_level: 20,
+            discovery_interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceSchedule {
+    last_level: Option<u8>,
+    /// Consecutive polls that saw the same level.
+    stable_polls: u32,
+    next_due: Instant,
+}
+
+/// When each device is next due. Every poll that sees an unchanged level
+/// doubles the device's interval, up to `max_interval`.
+#[derive(Debug, Clone)]
+pub struct PollSchedule {
+    config: PollConfig,
+    devices: HashMap<String, DeviceSchedule>,
+}
+
+impl PollSchedule {
+    pub fn new(config: PollConfig) -> Self {
+        Self {
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Devices that have never been polled are always due.
+    pub fn is_due(&self, mac_address: &str, now: Instant) -> bool {
+        self.devices
+            .get(mac_address)
+            .is_none_or(|device| now >= device.next_due)
+    }
+
+    /// Records the level seen by a poll at `now` and schedules the next one.
+    pub fn record(&mut self, mac_address: &str, level: Option<u8>, now: Instant) {
+        let previous = self.devices.get(mac_address).copied();
+        let stable_polls = match previous {
+            Some(previous) if previous.last_level == level => previous.stable_polls + 1,
+            _ => 0,
+        };
+        let interval = self.interval(level, stable_polls);
+        self.devices.insert(
+            mac_address.to_string(),
+            DeviceSchedule {
+                last_level: level,
+                stable_polls,
+                next_due: now + interval,
+            },
+        );
+    }
+
+    /// Records a poll of a device whose backend pushes changes. It is only
+    /// re-read every `max_interval`, in case notifications silently stop.
+    pub fn record_subscribed(&mut self, mac_address: &str, level: Option<u8>, now: Instant) {
+        self.devices.insert(
+            mac_address.to_string(),
+            DeviceSchedule {
+                last_level: level,
+                stable_polls: 0,
+                next_due: now + self.config.max_interval,
+            },
+        );
+    }
+
+    /// Drops devices that are no longer present.
+    pub fn retain(&mut self, present: impl Fn(&str) -> bool) {
+        self.devices.retain(|mac_address, _| present(mac_address));
+    }
+
+    /// When the next poll should run: the earliest due device, but no later
+    /// than the next discovery.
+    pub fn next_wakeup(&self, now: Instant) -> Instant {
+        self.devices
+            .values()
+            .map(|device| device.next_due)
+            .min()
+            .unwrap_or(now)
+            .min(now + self.config.discovery_interval)
+    }
+
+    /// Switches to new bounds. Devices due later than the new interval
+    /// allows are brought forward.
+    pub fn set_config(&mut self, config: PollConfig, now: Instant) {
+        self.config = config;
+        let intervals: Vec<(String, Duration)> = self
+            .devices
+            .iter()
+            .map(|(mac_address, device)| (mac_address.clone(), self.interval(device.last_level, device.stable_polls)))
+            .collect();
+        for (mac_address, interval) in intervals {
+            if let Some(device) = self.devices.get_mut(&mac_address) {
+                device.next_due = device.next_due.min(now + interval);
+            }
+        }
+    }
+
+    pub fn interval_for(&self, mac_address: &str) -> Option<Duration> {
+        self.devices
+            .get(mac_address)
+            .map(|device| self.interval(device.last_level, device.stable_polls))
+    }
+
+    fn interval(&self, level: Option<u8>, stable_polls: u32) -> Duration {
+        match level {
+            None => self.config.max_interval,
+            Some(level) if level <= self.config.low_level => self.config.min_interval,
+            Some(_) => self
+                .config
+                .min_interval
+                .saturating_mul(1 << stable_polls.min(16))
+                .min(self.config.max_interval),
+        }
+    }
+}
+
+type PollCallback = Box<dyn Fn(&[BluetoothDevice]) + Send + Sync>;
+
+/// Refreshes devices on their own schedule until the task is dropped. Each
+/// poll is recorded in the monitor's history and saved to its store.
+/// Devices whose backend notifies are only polled as a fallback.
+pub struct Poller {
+    monitor: Arc<BatteryMonitor>,
+    schedule: PollSchedule,
+    on_poll: Option<PollCallback>,
+    config_updates: Option<watch::Receiver<PollConfig>>,
+}
+
+impl Poller {
+    pub fn new(monitor: Arc<BatteryMonitor>, config: PollConfig) -> Self {
+        Self {
+            monitor,
+            schedule: PollSchedule::new(config),
+            on_poll: None,
+            config_updates: None,
+        }
+    }
+
+    /// Called with the full device list after every poll.
+    pub fn on_poll(mut self, callback: impl Fn(&[BluetoothDevice]) + Send + Sync + 'static) -> Self {
+        self.on_poll = Some(Box::new(callback));
+        self
+    }
+
+    /// Applies every `PollConfig` sent on `updates` while running, e.g. from
+    /// a reloaded configuration file.
+    pub fn with_config_updates(mut self, updates: watch::Receiver<PollConfig>) -> Self {
+        self.config_updates = Some(updates);
+        self
+    }
+
+    /// Runs one poll: discovery, then battery reads for the devices that are due.
+    pub async fn poll(&mut self) -> Vec<BluetoothDevice> {
+        let now = Instant::now();
+        let schedule = &self.schedule;
+        let devices = self
+            .monitor
+            .refresh_where(|mac_address| schedule.is_due(mac_address, now))
+            .await;
+
+        let due: Vec<&BluetoothDevice> = devices
+            .iter()
+            .filter(|device| self.schedule.is_due(&device.mac_address, now))
+            .collect();
+        for device in due {
+            if self.monitor.is_subscribed(&device.mac_address) {
+                self.schedule
+                    .record_subscribed(&device.mac_address, device.battery_level, now);
+            } else {
+                self.schedule.record(&device.mac_address, device.battery_level, now);
+            }
+        }
+        self.schedule
+            .retain(|mac_address| devices.iter().any(|d| d.mac_address == mac_address));
+
+        if let Some(on_poll) = &self.on_poll {
+            on_poll(&devices);
+        }
+        devices
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            self.poll().await;
+            let wakeup = self.schedule.next_wakeup(Instant::now());
+            let Some(updates) = &mut self.config_updates else {
+                tokio::time::sleep_until(wakeup.into()).await;
+                continue;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(wakeup.into()) => {}
+                Ok(()) = updates.changed() => {
+                    let config = *updates.borrow_and_update();
+                    self.schedule.set_config(config, Instant::now());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_battery::BatteryResult;
+    use crate::sources::mock::{MockSource, Scenario};
+    use crate::sources::{BatterySource, BatteryUpdate, DiscoveredDevice, SourceCapabilities, SourceChain};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    const MAC: &str = "02:00:00:00:00:01";
+
+    /// Simulated devices whose backend claims to push changes.
+    struct NotifyingSource(MockSource);
+
+    #[async_trait]
+    impl BatterySource for NotifyingSource {
+        fn backend(&self) -> crate::battery_history::BatteryBackend {
+            self.0.backend()
+        }
+
+        fn capabilities(&self) -> SourceCapabilities {
+            SourceCapabilities {
+                notifications: true,
+                ..self.0.capabilities()
+            }
+        }
+
+        async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+            self.0.discover().await
+        }
+
+        async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+            self.0.read_battery(device).await
+        }
+
+        async fn subscribe(&self, _device: &DiscoveredDevice, _updates: UnboundedSender<BatteryUpdate>) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    fn mouse_scenario() -> Scenario {
+        Scenario::from_json(
+            r#"{ "devices": [ { "name": "Sim Mouse", "mac_address": "02:00:00:00:00:01", "start_level": 90 } ] }"#,
+        )
+        .unwrap()
+    }
+
+    fn schedule() -> PollSchedule {
+        PollSchedule::new(PollConfig::default())
+    }
+
+    #[test]
+    fn test_stable_level_backs_off() {
+        let mut schedule = schedule();
+        let now = Instant::now();
+
+        schedule.record(MAC, Some(80), now);
+        assert_eq!(schedule.interval_for(MAC), Some(Duration::from_secs(60)));
+        schedule.record(MAC, Some(80), now);
+        assert_eq!(schedule.interval_for(MAC), Some(Duration::from_secs(120)));
+        for _ in 0..10 {
+            schedule.record(MAC, Some(80), now);
+        }
+        assert_eq!(schedule.interval_for(MAC), Some(Duration::from_secs(15 * 60)));
+
+        // A change resets to the fast interval
+        schedule.record(MAC, Some(79), now);
+        assert_eq!(schedule.interval_for(MAC), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_low_level_polls_fast() {
+        let mut schedule = schedule();
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            schedule.record(MAC, Some(15), now);
+        }
+        assert_eq!(schedule.interval_for(MAC), Some(Duration::from_secs(60)));
+        assert!(!schedule.is_due(MAC, now));
+        assert!(schedule.is_due(MAC, now + Duration::from_secs(60)));
+        assert!(schedule.is_due("02:00:00:00:00:09", now));
+    }
+
+    #[test]
+    fn test_new_config_brings_devices_forward() {
+        let mut schedule = schedule();
+        let now = Instant::now();
+        schedule.record(MAC, None, now);
+        assert!(!schedule.is_due(MAC, now + Duration::from_secs(5 * 60)));
+
+        let config = PollConfig {
+            max_interval: Duration::from_secs(2 * 60),
+            ..PollConfig::default()
+        };
+        schedule.set_config(config, now);
+        assert_eq!(schedule.interval_for(MAC), Some(Duration::from_secs(2 * 60)));
+        assert!(schedule.is_due(MAC, now + Duration::from_secs(2 * 60)));
+    }
+
+    #[test]
+    fn test_next_wakeup_is_bounded_by_discovery() {
+        let mut schedule = schedule();
+        let now = Instant::now();
+        assert_eq!(schedule.next_wakeup(now), now);
+
+        schedule.record(MAC, None, now);
+        assert_eq!(schedule.next_wakeup(now), now + Duration::from_secs(5 * 60));
+    }
+
+    #[test]
+    fn test_subscribed_devices_poll_slowly() {
+        let mut schedule = schedule();
+        let now = Instant::now();
+
+        schedule.record_subscribed(MAC, Some(15), now);
+        assert!(!schedule.is_due(MAC, now + Duration::from_secs(14 * 60)));
+        assert!(schedule.is_due(MAC, now + Duration::from_secs(15 * 60)));
+    }
+
+    #[tokio::test]
+    async fn test_poll_records_due_devices() {
+        let source = MockSource::with_manual_clock(mouse_scenario(), 1_700_000_000);
+        let monitor = Arc::new(BatteryMonitor::new(SourceChain::new().with_source(source), None));
+        let mut poller = Poller::new(monitor.clone(), PollConfig::default());
+
+        poller.poll().await;
+        poller.poll().await; // not due yet, keeps the last reading
+
+        assert_eq!(monitor.devices()[0].battery_level, Some(90));
+        assert_eq!(monitor.history(MAC).unwrap().samples().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_notifying_devices_fall_back_to_slow_polls() {
+        let source = NotifyingSource(MockSource::with_manual_clock(mouse_scenario(), 1_700_000_000));
+        let monitor = Arc::new(BatteryMonitor::new(SourceChain::new().with_source(source), None));
+        let (tx, _rx) = mpsc::unbounded_channel();
+        monitor.enable_updates(tx);
+        let mut poller = Poller::new(monitor.clone(), PollConfig::default());
+
+        poller.poll().await; // subscribes during the first read
+        assert!(monitor.is_subscribed(MAC));
+
+        // A polled device would be due again after `min_interval`
+        let now = Instant::now();
+        assert!(!poller.schedule.is_due(MAC, now + Duration::from_secs(5 * 60)));
+        assert!(poller.schedule.is_due(MAC, now + Duration::from_secs(15 * 60)));
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::bluetooth_battery::BatteryComponent;
+use crate::devices::BluetoothDevice;
+
+/// Output formats of the command line front end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(anyhow!("Unknown format '{}' (expected table, json or csv)", other)),
+        }
+    }
+}
+
+const CSV_HEADER: &str = "name,address,type,level,left,right,case,estimate,remaining_minutes,accuracy,\
+left_remaining_minutes,right_remaining_minutes,case_remaining_minutes,profile";
+
+const COMPONENTS: [BatteryComponent; 3] = [BatteryComponent::Left, BatteryComponent::Right, BatteryComponent::Case];
+
+pub fn render(devices: &[BluetoothDevice], format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Table => Ok(render_table(devices)),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(devices)? + "\n"),
+        OutputFormat::Csv => Ok(render_csv(devices)),
+    }
+}
+
+/// Devices with any component below `threshold` percent, or below their
+/// own threshold where the registry sets one.
+pub fn below_threshold(devices: &[BluetoothDevice], threshold: u8) -> Vec<&BluetoothDevice> {
+    devices.iter().filter(|device| device.is_low_battery(threshold)).collect()
+}
+
+fn render_table(devices: &[BluetoothDevice]) -> String {
+    if devices.is_empty() {
+        return "No Bluetooth devices found\n".to_string();
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<32} {:<17} {:<10} {:>5} {:>5} {:>5} {:>5}  ESTIMATE",
+        "NAME", "ADDRESS", "TYPE", "LEVEL", "LEFT", "RIGHT", "CASE"
+    );
+    for device in devices {
+        let _ = writeln!(
+            out,
+            "{:<32} {:<17} {:<10} {:>5} {:>5} {:>5} {:>5}  {} ({}){}",
+            device.name,
+            device.mac_address,
+            device.device_type,
+            percent(device.battery_level),
+            percent(device.battery.left),
+            percent(device.battery.right),
+            percent(device.battery.case),
+            device.battery_estimate,
+            device.accuracy,
+            device.profile.map_or(String::new(), |p| format!(" via {}", p.label())),
+        );
+        for component in &device.components {
+            let _ = writeln!(
+                out,
+                "  {:<30} {:>5}  {} ({})",
+                component.component.label(),
+                format!("{}%", component.level),
+                component.battery_estimate,
+                component.accuracy,
+            );
+        }
+    }
+    out
+}
+
+fn render_csv(devices: &[BluetoothDevice]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", CSV_HEADER);
+    for device in devices {
+        let mut fields = vec![
+            csv_field(&device.name),
+            csv_field(&device.mac_address),
+            csv_field(device.device_type.label()),
+            number(device.battery_level),
+            number(device.battery.left),
+            number(device.battery.right),
+            number(device.battery.case),
+            csv_field(&device.battery_estimate),
+            number(device.remaining_minutes),
+            csv_field(&device.accuracy),
+        ];
+        fields.extend(
+            COMPONENTS
+                .iter()
+                .map(|component| number(device.component(*component).map(|c| c.remaining_minutes))),
+        );
+        fields.push(csv_field(device.profile.map_or("", |p| p.label())));
+        let _ = writeln!(out, "{}", fields.join(","));
+    }
+    out
+}
+
+fn percent(level: Option<u8>) -> String {
+    level.map_or("N/A".to_string(), |l| format!("{}%", l))
+}
+
+fn number<T: ToString>(value: Option<T>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
+
+/// Quotes a field when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_battery::BatteryResult;
+    use crate::device_type::DeviceType;
+    use crate::devices::ComponentStatus;
+    use crate::rfcomm_profile::RfcommProfile;
+
+    fn earbuds() -> BluetoothDevice {
+        BluetoothDevice {
+            name: "Buds, \"Pro\"".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            device_type: DeviceType::Earbuds,
+            battery_level: Some(80),
+            battery: BatteryResult {
+                overall: None,
+                left: Some(80),
+                right: Some(15),
+                case: Some(100),
+            },
+            battery_estimate: "2h 30m".to_string(),
+            remaining_minutes: Some(150),
+            accuracy: "Estimated".to_string(),
+            low_battery_threshold: None,
+            profile: Some(RfcommProfile::Handsfree),
+            icon: None,
+            components: vec![
+                ComponentStatus {
+                    component: BatteryComponent::Left,
+                    level: 80,
+                    battery_estimate: "4h 0m".to_string(),
+                    remaining_minutes: 240,
+                    accuracy: "Estimated".to_string(),
+                },
+                ComponentStatus {
+                    component: BatteryComponent::Right,
+                    level: 15,
+                    battery_estimate: "0h 45m".to_string(),
+                    remaining_minutes: 45,
+                    accuracy: "Approximate".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_csv_quotes_and_leaves_missing_values_empty() {
+        let mut device = earbuds();
+        device.remaining_minutes = None;
+        device.battery.case = None;
+
+        let csv = render(&[device], OutputFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(
+            lines[1],
+            "\"Buds, \"\"Pro\"\"\",00:11:22:33:44:55,Earbuds,80,80,15,,2h 30m,,Estimated,240,45,,Hands-Free"
+        );
+    }
+
+    #[test]
+    fn test_json_includes_components() {
+        let json = render(&[earbuds()], OutputFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["battery"]["right"], 15);
+        assert_eq!(value[0]["remaining_minutes"], 150);
+        assert_eq!(value[0]["components"][1]["component"], "Right");
+        assert_eq!(value[0]["components"][1]["remaining_minutes"], 45);
+        assert_eq!(value[0]["profile"], "Handsfree");
+    }
+
+    #[test]
+    fn test_table_lists_components() {
+        let table = render(&[earbuds()], OutputFormat::Table).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].ends_with("2h 30m (Estimated) via Hands-Free"));
+        assert!(lines[3].trim_start().starts_with("Right"));
+        assert!(lines[3].ends_with("15%  0h 45m (Approximate)"));
+    }
+
+    #[test]
+    fn test_threshold_uses_lowest_component() {
+        let devices = [earbuds()];
+        assert_eq!(below_threshold(&devices, 20).len(), 1);
+        assert!(below_threshold(&devices, 15).is_empty());
+
+        let devices = [BluetoothDevice {
+            low_battery_threshold: Some(30),
+            ..earbuds()
+        }];
+        assert_eq!(below_threshold(&devices, 0).len(), 1);
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
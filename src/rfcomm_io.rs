@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How often a pending non-blocking call is retried.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Limits of each step of an RFCOMM battery query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RfcommTimeouts {
+    /// Includes the SDP lookup when the channel is not cached.
+    pub connect: Duration,
+    /// Longest silence while waiting for the peer.
+    pub read: Duration,
+    pub write: Duration,
+    /// Whole query of one device, however chatty it is.
+    pub session: Duration,
+}
+
+impl Default for RfcommTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(5),
+            write: Duration::from_secs(2),
+            session: Duration::from_secs(20),
+        }
+    }
+}
+
+/// Outcome of one try at a non-blocking socket call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attempt<T> {
+    Ready(T),
+    WouldBlock,
+}
+
+/// The error `until_ready` gives when `timeout` passes, so callers can tell
+/// a silent peer from a failed connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+    pub operation: String,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.operation, self.timeout)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Whether `error` is a `TimedOut`.
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    error.is::<TimedOut>()
+}
+
+/// Retries `attempt` until it is ready, fails, or `timeout` passes. Sleeps
+/// between tries instead of blocking, so other devices keep going, and
+/// dropping the future cancels the operation.
+pub async fn until_ready<T>(
+    operation: &str,
+    timeout: Duration,
+    mut attempt: impl FnMut() -> Result<Attempt<T>>,
+) -> Result<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Attempt::Ready(value) = attempt()? {
+            return Ok(value);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(TimedOut {
+                operation: operation.to_string(),
+                timeout,
+            }
+            .into());
+        }
+        tokio::time::sleep(POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[tokio::test]
+    async fn test_retries_until_ready() {
+        let mut tries = 0;
+        let value = until_ready("read", Duration::from_secs(1), || {
+            tries += 1;
+            Ok(if tries < 3 { Attempt::WouldBlock } else { Attempt::Ready(tries) })
+        })
+        .await
+        .unwrap();
+        assert_eq!(value, 3);
+    }
+
+    #[tokio::test]
+    async fn test_times_out_and_passes_errors_through() {
+        let started = Instant::now();
+        let error = until_ready::<()>("connect", Duration::from_millis(50), || Ok(Attempt::WouldBlock))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("connect timed out"));
+        assert!(is_timeout(&error));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let error = until_ready::<()>("send", Duration::from_secs(1), || Err(anyhow!("reset")))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "reset");
+        assert!(!is_timeout(&error));
+    }
+}
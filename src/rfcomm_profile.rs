@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Bluetooth Base UUID, into which 16-bit service class IDs are placed.
+const BLUETOOTH_BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5F9B_34FB;
+
+/// RFCOMM services that can carry battery reports, in order of preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RfcommProfile {
+    Handsfree,
+    Headset,
+    SerialPort,
+    /// The Audio Gateway side of Hands-Free, offered by phones.
+    HandsfreeGateway,
+}
+
+impl RfcommProfile {
+    pub const ALL: [RfcommProfile; 4] = [
+        RfcommProfile::Handsfree,
+        RfcommProfile::Headset,
+        RfcommProfile::SerialPort,
+        RfcommProfile::HandsfreeGateway,
+    ];
+
+    /// 16-bit service class ID, as published in the device's SDP records.
+    pub fn service_class_id(&self) -> u16 {
+        match self {
+            RfcommProfile::Handsfree => 0x111E,
+            RfcommProfile::Headset => 0x1108,
+            RfcommProfile::SerialPort => 0x1101,
+            RfcommProfile::HandsfreeGateway => 0x111F,
+        }
+    }
+
+    pub fn service_class_uuid(&self) -> u128 {
+        BLUETOOTH_BASE_UUID | (self.service_class_id() as u128) << 96
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RfcommProfile::Handsfree => "Hands-Free",
+            RfcommProfile::Headset => "Headset",
+            RfcommProfile::SerialPort => "Serial Port",
+            RfcommProfile::HandsfreeGateway => "Hands-Free Audio Gateway",
+        }
+    }
+
+    /// Whether the device is the Audio Gateway on this service, so we take
+    /// the HF role to read it.
+    pub fn is_gateway(&self) -> bool {
+        matches!(self, RfcommProfile::HandsfreeGateway)
+    }
+}
+
+/// Where a device's service was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RfcommService {
+    pub profile: RfcommProfile,
+    /// RFCOMM channel SDP resolved to, when known.
+    pub channel: Option<u8>,
+}
+
+/// The service that last worked for each device, so later connections skip
+/// the SDP lookup and the profiles the device lacks.
+#[derive(Debug, Default)]
+pub struct ServiceCache {
+    services: Mutex<HashMap<String, RfcommService>>,
+}
+
+impl ServiceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, mac_address: &str) -> Option<RfcommService> {
+        self.services.lock().unwrap().get(mac_address).copied()
+    }
+
+    pub fn record(&self, mac_address: &str, service: RfcommService) {
+        self.services
+            .lock()
+            .unwrap()
+            .insert(mac_address.to_string(), service);
+    }
+
+    /// Drops a cached service that stopped working, e.g. after the device
+    /// was re-paired and its channels moved.
+    pub fn forget(&self, mac_address: &str) {
+        self.services.lock().unwrap().remove(mac_address);
+    }
+
+    /// Profiles to try for a device: the cached one first, then the others
+    /// in order of preference. Only gateway profiles are tried for a
+    /// `gateway` such as a phone, and only the others for anything else.
+    pub fn candidates(&self, mac_address: &str, gateway: bool) -> Vec<RfcommProfile> {
+        let mut profiles: Vec<_> = RfcommProfile::ALL
+            .into_iter()
+            .filter(|profile| profile.is_gateway() == gateway)
+            .collect();
+        if let Some(service) = self.get(mac_address).filter(|service| profiles.contains(&service.profile)) {
+            profiles.retain(|profile| *profile != service.profile);
+            profiles.insert(0, service.profile);
+        }
+        profiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: &str = "00:11:22:33:44:55";
+
+    #[test]
+    fn test_service_class_uuids() {
+        assert_eq!(
+            RfcommProfile::Handsfree.service_class_uuid(),
+            0x0000111E_0000_1000_8000_00805F9B34FB
+        );
+        assert_eq!(
+            RfcommProfile::SerialPort.service_class_uuid(),
+            0x00001101_0000_1000_8000_00805F9B34FB
+        );
+    }
+
+    #[test]
+    fn test_cached_profile_is_tried_first() {
+        let cache = ServiceCache::new();
+        assert_eq!(
+            cache.candidates(MAC, false),
+            [RfcommProfile::Handsfree, RfcommProfile::Headset, RfcommProfile::SerialPort]
+        );
+        assert_eq!(cache.candidates(MAC, true), [RfcommProfile::HandsfreeGateway]);
+
+        cache.record(MAC, RfcommService { profile: RfcommProfile::Headset, channel: Some(3) });
+        assert_eq!(
+            cache.candidates(MAC, false),
+            [RfcommProfile::Headset, RfcommProfile::Handsfree, RfcommProfile::SerialPort]
+        );
+        assert_eq!(cache.candidates(MAC, true), [RfcommProfile::HandsfreeGateway]);
+        assert_eq!(cache.get(MAC).unwrap().channel, Some(3));
+
+        cache.forget(MAC);
+        assert_eq!(cache.get(MAC), None);
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+use windows::Devices::Bluetooth::BluetoothLEDevice;
+
+use super::{BatteryReading, BatterySource, BatteryUpdate, DiscoveredDevice, SourceCapabilities};
+use crate::at_commands::parse_mac_address;
+use crate::battery_history::{unix_timestamp, BatteryBackend, ConnectionState};
+use crate::bluetooth_battery::{BatteryResult, BluetoothBatteryQuerier};
+use crate::uwp_bluetooth::subscribe_battery_levels;
+
+/// Every GATT Battery Service instance of a device, read by address.
+pub struct BleSource;
+
+#[async_trait]
+impl BatterySource for BleSource {
+    fn backend(&self) -> BatteryBackend {
+        BatteryBackend::Ble
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            discovery: false,
+            battery: true,
+            components: true,
+            notifications: true,
+        }
+    }
+
+    async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+        BluetoothBatteryQuerier::new(device.mac_address.clone())
+            .read_gatt_battery()
+            .await
+    }
+
+    /// Enables Battery Level notifications. Classic devices without a GATT
+    /// Battery Service, and services that cannot notify, report `false` so
+    /// the device stays on the polling schedule.
+    async fn subscribe(&self, device: &DiscoveredDevice, updates: UnboundedSender<BatteryUpdate>) -> Result<bool> {
+        let address = parse_mac_address(&device.mac_address)?;
+        let mac_address = device.mac_address.clone();
+        let sender = updates.clone();
+
+        let notifications = tokio::task::spawn_blocking(move || {
+            let Ok(ble_device) = BluetoothLEDevice::FromBluetoothAddressAsync(address).and_then(|op| op.get()) else {
+                return Ok(None);
+            };
+            subscribe_battery_levels(ble_device, move |battery| {
+                let _ = sender.send(BatteryUpdate {
+                    mac_address: mac_address.clone(),
+                    reading: BatteryReading {
+                        battery,
+                        source: BatteryBackend::Ble,
+                        connection: ConnectionState::Connected,
+                        observed_at: unix_timestamp(),
+                        profile: None,
+                    },
+                });
+            })
+        })
+        .await??;
+
+        let Some(notifications) = notifications else {
+            return Ok(false);
+        };
+        // Keep the handlers registered until the receiving side goes away
+        tokio::spawn(async move {
+            updates.closed().await;
+            drop(notifications);
+        });
+        Ok(true)
+    }
+}
@@ -0,0 +1,638 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::{BatteryReading, BatterySource, BatteryUpdate, DiscoveredDevice, SourceCapabilities};
+use crate::address::BluetoothAddress;
+use crate::battery_history::{unix_timestamp, BatteryBackend, ConnectionState};
+use crate::bluetooth_battery::BatteryResult;
+
+/// The `org.bluez.Device1` and `org.bluez.Battery1` properties of one device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BluezDevice {
+    /// D-Bus object path, e.g. `/org/bluez/hci0/dev_00_11_22_33_44_55`.
+    pub path: String,
+    pub address: String,
+    pub name: Option<String>,
+    pub alias: String,
+    pub connected: bool,
+    /// `org.bluez.Battery1.Percentage`, absent when the device has no battery interface.
+    pub battery_percentage: Option<u8>,
+    /// `Class`, set for Classic devices.
+    pub class: Option<u32>,
+    /// `Appearance`, set for LE devices that expose it.
+    pub appearance: Option<u16>,
+}
+
+/// The parts of the BlueZ D-Bus API this backend uses. Single devices are
+/// addressed by object path, which also names their adapter.
+#[async_trait]
+pub trait BluezBus: Send + Sync {
+    async fn devices(&self) -> Result<Vec<BluezDevice>>;
+
+    /// The device at `path`, without enumerating the others.
+    async fn device(&self, path: &str) -> Result<BluezDevice>;
+
+    /// Sends every `Battery1.Percentage` change reported through
+    /// `PropertiesChanged` for the device at `path`.
+    async fn watch_battery(&self, path: &str) -> Result<UnboundedReceiver<u8>>;
+}
+
+/// Devices known to BlueZ (Linux).
+pub struct BluezSource<B> {
+    bus: B,
+    /// Object path of every device seen by the last discovery.
+    paths: Mutex<HashMap<BluetoothAddress, String>>,
+}
+
+impl<B: BluezBus> BluezSource<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            paths: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Object path of the device at `mac_address`, discovering again if it
+    /// has not been seen yet.
+    async fn path_of(&self, mac_address: &str) -> Result<(BluetoothAddress, String)> {
+        let address: BluetoothAddress = mac_address.parse()?;
+        if let Some(path) = self.paths.lock().unwrap().get(&address) {
+            return Ok((address, path.clone()));
+        }
+        self.discover().await?;
+        let path = self.paths.lock().unwrap().get(&address).cloned();
+        path.map(|path| (address, path))
+            .ok_or_else(|| anyhow!("BlueZ does not know {}", mac_address))
+    }
+}
+
+#[async_trait]
+impl<B: BluezBus> BatterySource for BluezSource<B> {
+    fn backend(&self) -> BatteryBackend {
+        BatteryBackend::Bluez
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            discovery: true,
+            battery: true,
+            components: false,
+            notifications: true,
+        }
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        let devices = self.bus.devices().await?;
+        *self.paths.lock().unwrap() = devices
+            .iter()
+            .filter_map(|device| Some((BluetoothAddress::from_bluez_path(&device.path)?, device.path.clone())))
+            .collect();
+
+        Ok(devices
+            .iter()
+            .filter(|device| device.connected)
+            .filter_map(to_discovered_device)
+            .collect())
+    }
+
+    async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+        let (address, path) = self.path_of(&device.mac_address).await?;
+        let found = match self.bus.device(&path).await {
+            Ok(found) => found,
+            Err(e) => {
+                // It may have moved to another adapter; look again next time
+                self.paths.lock().unwrap().remove(&address);
+                return Err(e);
+            }
+        };
+
+        let mut result = BatteryResult::new();
+        result.overall = found.battery_percentage.filter(|_| found.connected);
+        Ok(result)
+    }
+
+    async fn subscribe(&self, device: &DiscoveredDevice, updates: UnboundedSender<BatteryUpdate>) -> Result<bool> {
+        let (_, path) = self.path_of(&device.mac_address).await?;
+        let mut levels = self.bus.watch_battery(&path).await?;
+        let mac_address = device.mac_address.clone();
+
+        tokio::spawn(async move {
+            while let Some(level) = levels.recv().await {
+                let update = BatteryUpdate {
+                    mac_address: mac_address.clone(),
+                    reading: BatteryReading {
+                        battery: BatteryResult {
+                            overall: Some(level),
+                            left: None,
+                            right: None,
+                            case: None,
+                        },
+                        source: BatteryBackend::Bluez,
+                        connection: ConnectionState::Connected,
+                        observed_at: unix_timestamp(),
+                        profile: None,
+                    },
+                };
+                if updates.send(update).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(true)
+    }
+}
+
+fn to_discovered_device(device: &BluezDevice) -> Option<DiscoveredDevice> {
+    // The object path is authoritative; skip anything that does not match it.
+    let address = address_from_path(&device.path)?;
+    if device.address.parse::<BluetoothAddress>().ok()?.to_string() != address {
+        return None;
+    }
+
+    Some(DiscoveredDevice {
+        name: device.name.clone().unwrap_or_else(|| device.alias.clone()),
+        mac_address: address,
+        connection: if device.connected {
+            ConnectionState::Connected
+        } else {
+            ConnectionState::Disconnected
+        },
+        source: BatteryBackend::Bluez,
+        battery: device.battery_percentage.map(|level| BatteryResult {
+            overall: Some(level),
+            left: None,
+            right: None,
+            case: None,
+        }),
+        class_of_device: device.class,
+        appearance: device.appearance,
+    })
+}
+
+/// `/org/bluez/hci0/dev_00_11_22_33_44_55` -> `00:11:22:33:44:55`
+pub fn address_from_path(path: &str) -> Option<String> {
+    BluetoothAddress::from_bluez_path(path).map(|address| address.to_string())
+}
+
+/// `/org/bluez/hci1/dev_00_11_22_33_44_55` -> `hci1`
+pub fn adapter_from_path(path: &str) -> Option<&str> {
+    let mut nodes = path.strip_prefix("/org/bluez/")?.split('/');
+    let adapter = nodes.next().filter(|adapter| !adapter.is_empty())?;
+    nodes.next()?;
+    Some(adapter)
+}
+
+/// `BluezBus` over the system bus through bluer.
+#[cfg(feature = "bluer")]
+#[derive(Default)]
+pub struct BluerBus {
+    /// Opened on first use and then kept; a failed attempt is retried on
+    /// the next call.
+    session: tokio::sync::OnceCell<bluer::Session>,
+}
+
+#[cfg(feature = "bluer")]
+impl BluerBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    async fn session(&self) -> Result<&bluer::Session> {
+        Ok(self.session.get_or_try_init(bluer::Session::new).await?)
+    }
+
+    async fn device_at(&self, path: &str) -> Result<bluer::Device> {
+        let adapter_name = adapter_from_path(path).ok_or_else(|| anyhow!("Not a BlueZ device path: {}", path))?;
+        let address = BluetoothAddress::from_bluez_path(path)
+            .ok_or_else(|| anyhow!("Not a BlueZ device path: {}", path))?;
+        let adapter = self.session().await?.adapter(adapter_name)?;
+        Ok(adapter.device(bluer::Address(address.bytes()))?)
+    }
+}
+
+#[cfg(feature = "bluer")]
+async fn read_device(device: &bluer::Device) -> Result<BluezDevice> {
+    let address = device.address();
+    Ok(BluezDevice {
+        path: format!(
+            "/org/bluez/{}/dev_{}",
+            device.adapter_name(),
+            address.to_string().replace(':', "_")
+        ),
+        address: address.to_string(),
+        name: device.name().await?,
+        alias: device.alias().await?,
+        connected: device.is_connected().await?,
+        battery_percentage: device.battery_percentage().await?,
+        class: device.class().await?,
+        appearance: device.appearance().await?,
+    })
+}
+
+#[cfg(feature = "bluer")]
+#[async_trait]
+impl BluezBus for BluerBus {
+    async fn devices(&self) -> Result<Vec<BluezDevice>> {
+        let session = self.session().await?;
+        let mut devices = Vec::new();
+
+        for adapter_name in session.adapter_names().await? {
+            let adapter = session.adapter(&adapter_name)?;
+            for address in adapter.device_addresses().await? {
+                // One device failing to answer must not hide the others
+                match read_device(&adapter.device(address)?).await {
+                    Ok(device) => devices.push(device),
+                    Err(e) => eprintln!("Skipping BlueZ device {} on {}: {:#}", address, adapter_name, e),
+                }
+            }
+        }
+
+        Ok(devices)
+    }
+
+    async fn device(&self, path: &str) -> Result<BluezDevice> {
+        read_device(&self.device_at(path).await?).await
+    }
+
+    async fn watch_battery(&self, path: &str) -> Result<UnboundedReceiver<u8>> {
+        use bluer::{DeviceEvent, DeviceProperty};
+        use futures::StreamExt;
+
+        let events = self.device_at(path).await?.events().await?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut events = Box::pin(events);
+            while let Some(event) = events.next().await {
+                if let DeviceEvent::PropertyChanged(DeviceProperty::BatteryPercentage(level)) = event {
+                    if tx.send(level).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_type::DeviceType;
+
+    /// In-process stand-in for the `org.bluez` service.
+    #[derive(Default)]
+    struct MockBus {
+        devices: Vec<BluezDevice>,
+        watchers: Mutex<HashMap<String, UnboundedSender<u8>>>,
+    }
+
+    impl MockBus {
+        fn emit_battery(&self, path: &str, level: u8) {
+            if let Some(tx) = self.watchers.lock().unwrap().get(path) {
+                let _ = tx.send(level);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl BluezBus for MockBus {
+        async fn devices(&self) -> Result<Vec<BluezDevice>> {
+            Ok(self.devices.clone())
+        }
+
+        async fn device(&self, path: &str) -> Result<BluezDevice> {
+            self.devices
+                .iter()
+                .find(|d| d.path == path)
+                .cloned()
+                .ok_or_else(|| anyhow!("No such object {}", path))
+        }
+
+        async fn watch_battery(&self, path: &str) -> Result<UnboundedReceiver<u8>> {
+            self.device(path).await?;
+            let (tx, rx) = mpsc::unbounded_channel();
+            self.watchers.lock().unwrap().insert(path.to_string(), tx);
+            Ok(rx)
+        }
+    }
+
+    fn bluez_device(address: &str, name: Option<&str>, connected: bool, battery: Option<u8>) -> BluezDevice {
+        BluezDevice {
+            path: format!("/org/bluez/hci0/dev_{}", address.replace(':', "_")),
+            address: address.to_string(),
+            name: name.map(str::to_string),
+            alias: "Alias".to_string(),
+            connected,
+            battery_percentage: battery,
+            class: None,
+            appearance: None,
+        }
+    }
+
+    fn mock_bus() -> MockBus {
+        MockBus {
+            devices: vec![
+                bluez_device("00:11:22:33:44:55", Some("WH-1000XM4"), true, Some(70)),
+                BluezDevice {
+                    class: Some(0x240404),
+                    ..bluez_device("66:77:88:99:AA:BB", None, true, None)
+                },
+                bluez_device("CC:DD:EE:FF:00:11", Some("Old Mouse"), false, Some(10)),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_address_from_path() {
+        assert_eq!(
+            address_from_path("/org/bluez/hci0/dev_00_1a_7d_da_71_13").as_deref(),
+            Some("00:1A:7D:DA:71:13")
+        );
+        assert_eq!(address_from_path("/org/bluez/hci0"), None);
+        assert_eq!(address_from_path("/org/bluez/hci0/dev_00_11_22"), None);
+        assert_eq!(adapter_from_path("/org/bluez/hci1/dev_00_1A_7D_DA_71_13"), Some("hci1"));
+        assert_eq!(adapter_from_path("/org/bluez/hci1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_battery_looks_up_one_device() {
+        let source = BluezSource::new(mock_bus());
+        let devices = source.discover().await.unwrap();
+
+        assert_eq!(source.read_battery(&devices[0]).await.unwrap().overall, Some(70));
+        let unknown = DiscoveredDevice {
+            mac_address: "12:34:56:78:9A:BC".to_string(),
+            ..devices[0].clone()
+        };
+        assert!(source.read_battery(&unknown).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_discovers_connected_devices() {
+        let source = BluezSource::new(mock_bus());
+        let devices = source.discover().await.unwrap();
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "WH-1000XM4");
+        assert_eq!(devices[0].battery.as_ref().unwrap().overall, Some(70));
+        assert_eq!(devices[1].name, "Alias");
+        assert_eq!(devices[1].device_type(), DeviceType::Headset);
+        assert!(devices[1].battery.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_property_changes_are_forwarded() {
+        let source = BluezSource::new(mock_bus());
+        let devices = source.discover().await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        assert!(source.subscribe(&devices[0], tx).await.unwrap());
+        source.bus.emit_battery("/org/bluez/hci0/dev_00_11_22_33_44_55", 65);
+
+        let update = rx.recv().await.unwrap();
+        assert_eq!(update.mac_address, "00:11:22:33:44:55");
+        assert_eq!(update.reading.battery.overall, Some(65));
+        assert_eq!(update.reading.source, BatteryBackend::Bluez);
+    }
+}
+
+/// `BluerBus` against a mock `org.bluez` service on a private bus. Needs
+/// `dbus-daemon` on the PATH; the test is skipped without it.
+#[cfg(all(test, feature = "bluer"))]
+mod dbus_tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use zbus::message::Type;
+    use zbus::zvariant::{OwnedObjectPath, Value};
+
+    /// A `dbus-daemon` of our own, stopped on drop.
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockDevice {
+        adapter: &'static str,
+        address: &'static str,
+        name: Option<&'static str>,
+        connected: bool,
+        battery: Option<u8>,
+        /// Fails every property read, like a device stuck in BlueZ.
+        broken: bool,
+    }
+
+    impl MockDevice {
+        fn path(&self) -> String {
+            format!("/org/bluez/{}/dev_{}", self.adapter, self.address.replace(':', "_"))
+        }
+
+        fn interfaces(&self) -> HashMap<&'static str, HashMap<&'static str, Value<'static>>> {
+            let mut device = HashMap::from([
+                ("Address", Value::from(self.address)),
+                ("Alias", Value::from(self.name.unwrap_or(self.address))),
+                ("Connected", Value::from(self.connected)),
+            ]);
+            if let Some(name) = self.name {
+                device.insert("Name", Value::from(name));
+            }
+            let mut interfaces = HashMap::from([("org.bluez.Device1", device)]);
+            if let Some(level) = self.battery {
+                interfaces.insert("org.bluez.Battery1", HashMap::from([("Percentage", Value::from(level))]));
+            }
+            interfaces
+        }
+    }
+
+    /// Answers `GetManagedObjects` and `Properties.Get` like bluetoothd.
+    struct MockBluez {
+        connection: zbus::Connection,
+        devices: Arc<Mutex<Vec<MockDevice>>>,
+    }
+
+    impl MockBluez {
+        async fn serve(address: &str, devices: Vec<MockDevice>) -> zbus::Result<Self> {
+            let connection = zbus::connection::Builder::address(address)?
+                .name("org.bluez")?
+                .build()
+                .await?;
+            let devices = Arc::new(Mutex::new(devices));
+
+            let mut messages = zbus::MessageStream::from(&connection);
+            let (conn, state) = (connection.clone(), devices.clone());
+            tokio::spawn(async move {
+                while let Some(Ok(message)) = messages.next().await {
+                    if message.message_type() == Type::MethodCall {
+                        let devices = state.lock().unwrap().clone();
+                        let _ = answer(&conn, &message, &devices).await;
+                    }
+                }
+            });
+
+            Ok(Self { connection, devices })
+        }
+
+        async fn set_battery(&self, path: &str, level: u8) {
+            for device in self.devices.lock().unwrap().iter_mut() {
+                if device.path() == path {
+                    device.battery = Some(level);
+                }
+            }
+            let changed = HashMap::from([("Percentage", Value::from(level))]);
+            self.connection
+                .emit_signal(
+                    None::<()>,
+                    path,
+                    "org.freedesktop.DBus.Properties",
+                    "PropertiesChanged",
+                    &("org.bluez.Battery1", changed, Vec::<String>::new()),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn answer(connection: &zbus::Connection, call: &zbus::Message, devices: &[MockDevice]) -> zbus::Result<()> {
+        let header = call.header();
+        let path = header.path().map(|p| p.to_string()).unwrap_or_default();
+        let member = header.member().map(|m| m.to_string()).unwrap_or_default();
+
+        match member.as_str() {
+            "GetManagedObjects" if path == "/" => {
+                let mut objects: HashMap<OwnedObjectPath, HashMap<&str, HashMap<&str, Value>>> = ["hci0", "hci1"]
+                    .into_iter()
+                    .map(|adapter| {
+                        let path = OwnedObjectPath::try_from(format!("/org/bluez/{}", adapter)).unwrap();
+                        (path, HashMap::from([("org.bluez.Adapter1", HashMap::new())]))
+                    })
+                    .collect();
+                for device in devices {
+                    objects.insert(OwnedObjectPath::try_from(device.path()).unwrap(), device.interfaces());
+                }
+                connection.reply(call, &objects).await
+            }
+            "Get" => {
+                let (interface, property): (String, String) = call.body().deserialize()?;
+                let Some(device) = devices.iter().find(|d| d.path() == path) else {
+                    return connection
+                        .reply_error(call, "org.freedesktop.DBus.Error.UnknownObject", &("No such object",))
+                        .await;
+                };
+                if device.broken {
+                    return connection
+                        .reply_error(call, "org.freedesktop.DBus.Error.Failed", &("Device is not responding",))
+                        .await;
+                }
+                match device.interfaces().get(interface.as_str()).and_then(|p| p.get(property.as_str())) {
+                    Some(value) => connection.reply(call, value).await,
+                    // bluetoothd's answer for absent optional properties
+                    None => {
+                        connection
+                            .reply_error(call, "org.freedesktop.DBus.Error.InvalidArgs", &("No such property",))
+                            .await
+                    }
+                }
+            }
+            _ => {
+                connection
+                    .reply_error(call, "org.freedesktop.DBus.Error.UnknownMethod", &("Not implemented",))
+                    .await
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bluer_bus_against_mock_bluez() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+        // bluer always connects to the system bus
+        std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &bus.address);
+
+        let device = |adapter, address, name, connected, battery| MockDevice {
+            adapter,
+            address,
+            name,
+            connected,
+            battery,
+            broken: false,
+        };
+        let service = MockBluez::serve(
+            &bus.address,
+            vec![
+                device("hci0", "00:11:22:33:44:55", Some("WH-1000XM4"), true, Some(70)),
+                MockDevice {
+                    broken: true,
+                    ..device("hci0", "66:77:88:99:AA:BB", Some("Stuck"), true, Some(10))
+                },
+                device("hci0", "CC:DD:EE:FF:00:11", Some("Old Mouse"), false, Some(10)),
+                device("hci1", "12:34:56:78:9A:BC", None, true, Some(55)),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let source = BluezSource::new(BluerBus::new());
+        let devices = source.discover().await.unwrap();
+
+        // The stuck device is skipped without hiding the others
+        let mut found: Vec<_> = devices.iter().map(|d| (d.mac_address.as_str(), d.name.as_str())).collect();
+        found.sort();
+        assert_eq!(found, [("00:11:22:33:44:55", "WH-1000XM4"), ("12:34:56:78:9A:BC", "12:34:56:78:9A:BC")]);
+
+        let second_adapter = devices.iter().find(|d| d.mac_address == "12:34:56:78:9A:BC").unwrap();
+        assert_eq!(source.read_battery(second_adapter).await.unwrap().overall, Some(55));
+
+        // Updates arrive for devices on adapters other than the default one.
+        // Re-sent until seen, since the subscription is set up asynchronously.
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        assert!(source.subscribe(second_adapter, tx).await.unwrap());
+        let update = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                service.set_battery("/org/bluez/hci1/dev_12_34_56_78_9A_BC", 54).await;
+                if let Ok(Some(update)) = tokio::time::timeout(Duration::from_millis(200), rx.recv()).await {
+                    return update;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(update.mac_address, "12:34:56:78:9A:BC");
+        assert_eq!(update.reading.battery.overall, Some(54));
+    }
+}
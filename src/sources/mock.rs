@@ -0,0 +1,404 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::{BatterySource, DiscoveredDevice, SourceCapabilities};
+use crate::battery_history::{unix_timestamp, BatteryBackend, ConnectionState};
+use crate::bluetooth_battery::{BatteryComponent, BatteryResult};
+
+/// A scripted set of devices, loaded from a JSON scenario file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    /// Simulated seconds per real second.
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64,
+    pub devices: Vec<ScenarioDevice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioDevice {
+    pub name: String,
+    pub mac_address: String,
+    pub start_level: u8,
+    /// Level changes applied one after another; negative rates drain.
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+    /// Start over from `start_level` once all phases have run.
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(default = "default_true")]
+    pub connected: bool,
+    /// Devices that never report a level.
+    #[serde(default = "default_true")]
+    pub reports_battery: bool,
+    /// Separate levels (e.g. earbuds and case), reported instead of the
+    /// device's own level when present.
+    #[serde(default)]
+    pub components: Vec<ScenarioComponent>,
+    #[serde(default)]
+    pub class_of_device: Option<u32>,
+    #[serde(default)]
+    pub appearance: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioComponent {
+    pub component: BatteryComponent,
+    pub start_level: u8,
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Phase {
+    pub duration_mins: f64,
+    /// Percent per hour, positive while charging.
+    pub rate_per_hour: f64,
+}
+
+/// Largest accepted `time_scale`: a simulated year in about half a minute.
+const MAX_TIME_SCALE: f64 = 1_000_000.0;
+
+fn default_time_scale() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Scenario {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let scenario: Scenario = serde_json::from_str(json)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Checks what the types alone do not, naming the offending value.
+    pub fn validate(&self) -> Result<()> {
+        if !(self.time_scale.is_finite() && self.time_scale > 0.0 && self.time_scale <= MAX_TIME_SCALE) {
+            return Err(anyhow!(
+                "time_scale must be greater than 0 and at most {}, not {}",
+                MAX_TIME_SCALE,
+                self.time_scale
+            ));
+        }
+        for device in &self.devices {
+            let check = || -> Result<()> {
+                validate_levels(device.start_level, &device.phases)?;
+                for component in &device.components {
+                    validate_levels(component.start_level, &component.phases)
+                        .with_context(|| format!("In component {:?}", component.component))?;
+                }
+                Ok(())
+            };
+            check().with_context(|| format!("Invalid scenario device '{}'", device.name))?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_levels(start_level: u8, phases: &[Phase]) -> Result<()> {
+    if start_level > 100 {
+        return Err(anyhow!("start_level must be 0-100, not {}", start_level));
+    }
+    for phase in phases {
+        if !(phase.duration_mins.is_finite() && phase.duration_mins >= 0.0) {
+            return Err(anyhow!("duration_mins must not be negative, not {}", phase.duration_mins));
+        }
+        if !phase.rate_per_hour.is_finite() {
+            return Err(anyhow!("rate_per_hour must be a number, not {}", phase.rate_per_hour));
+        }
+    }
+    Ok(())
+}
+
+impl ScenarioDevice {
+    /// Battery level after `elapsed` simulated time.
+    pub fn level_at(&self, elapsed: Duration) -> u8 {
+        level_after(self.start_level, &self.phases, self.repeat, elapsed)
+    }
+
+    /// Level of one component after `elapsed`; components follow the
+    /// device's `repeat` setting.
+    pub fn component_level_at(&self, component: &ScenarioComponent, elapsed: Duration) -> u8 {
+        level_after(component.start_level, &component.phases, self.repeat, elapsed)
+    }
+}
+
+fn level_after(start_level: u8, phases: &[Phase], repeat: bool, elapsed: Duration) -> u8 {
+    let total_mins: f64 = phases.iter().map(|p| p.duration_mins).sum();
+    let mut remaining_mins = elapsed.as_secs_f64() / 60.0;
+    if repeat && total_mins > 0.0 {
+        remaining_mins %= total_mins;
+    }
+
+    let mut level = start_level.min(100) as f64;
+    for phase in phases {
+        let mins = remaining_mins.min(phase.duration_mins);
+        level = (level + phase.rate_per_hour * mins / 60.0).clamp(0.0, 100.0);
+        remaining_mins -= mins;
+        if remaining_mins <= 0.0 {
+            break;
+        }
+    }
+    level.round() as u8
+}
+
+enum Clock {
+    /// Real time multiplied by the scenario's time scale.
+    Scaled { started: Instant, time_scale: f64 },
+    /// Advanced explicitly, for tests.
+    Manual { elapsed_secs: AtomicU64 },
+}
+
+/// Simulated backend for running the app without Bluetooth hardware.
+pub struct MockSource {
+    scenario: Scenario,
+    clock: Clock,
+    start_timestamp: u64,
+}
+
+impl MockSource {
+    /// `scenario` should have passed `Scenario::validate`; `from_file` does
+    /// that.
+    pub fn new(scenario: Scenario) -> Self {
+        let time_scale = scenario.time_scale;
+        Self {
+            scenario,
+            clock: Clock::Scaled {
+                started: Instant::now(),
+                time_scale,
+            },
+            start_timestamp: unix_timestamp(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self::new(Scenario::from_json(&json)?))
+    }
+
+    /// A source whose time only moves through `advance`.
+    pub fn with_manual_clock(scenario: Scenario, start_timestamp: u64) -> Self {
+        Self {
+            scenario,
+            clock: Clock::Manual {
+                elapsed_secs: AtomicU64::new(0),
+            },
+            start_timestamp,
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        if let Clock::Manual { elapsed_secs } = &self.clock {
+            elapsed_secs.fetch_add(duration.as_secs(), Ordering::SeqCst);
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        match &self.clock {
+            Clock::Scaled {
+                started,
+                time_scale,
+            } => Duration::try_from_secs_f64(started.elapsed().as_secs_f64() * time_scale).unwrap_or(Duration::MAX),
+            Clock::Manual { elapsed_secs } => Duration::from_secs(elapsed_secs.load(Ordering::SeqCst)),
+        }
+    }
+
+    fn battery_of(&self, device: &ScenarioDevice) -> BatteryResult {
+        let mut result = BatteryResult::new();
+        if !device.reports_battery {
+            return result;
+        }
+        if device.components.is_empty() {
+            result.overall = Some(device.level_at(self.elapsed()));
+        }
+        for component in &device.components {
+            let level = Some(device.component_level_at(component, self.elapsed()));
+            match component.component {
+                BatteryComponent::Overall => result.overall = level,
+                BatteryComponent::Left => result.left = level,
+                BatteryComponent::Right => result.right = level,
+                BatteryComponent::Case => result.case = level,
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl BatterySource for MockSource {
+    fn backend(&self) -> BatteryBackend {
+        BatteryBackend::Mock
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            discovery: true,
+            battery: true,
+            components: true,
+            notifications: false,
+        }
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        Ok(self
+            .scenario
+            .devices
+            .iter()
+            .map(|device| DiscoveredDevice {
+                name: device.name.clone(),
+                mac_address: device.mac_address.clone(),
+                connection: if device.connected {
+                    ConnectionState::Connected
+                } else {
+                    ConnectionState::Disconnected
+                },
+                source: BatteryBackend::Mock,
+                battery: Some(self.battery_of(device)),
+                class_of_device: device.class_of_device,
+                appearance: device.appearance,
+            })
+            .collect())
+    }
+
+    async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+        Ok(self
+            .scenario
+            .devices
+            .iter()
+            .find(|d| d.mac_address.eq_ignore_ascii_case(&device.mac_address))
+            .map(|d| self.battery_of(d))
+            .unwrap_or_else(BatteryResult::new))
+    }
+
+    fn now(&self) -> u64 {
+        self.start_timestamp.saturating_add(self.elapsed().as_secs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery_history::{BatteryHistory, BatterySample};
+    use crate::estimator::Accuracy;
+    use crate::sources::SourceChain;
+
+    const SAMPLE_SCENARIO: &str = include_str!("../../scenarios/earbuds-and-mouse.json");
+
+    fn scenario() -> Scenario {
+        Scenario::from_json(
+            r#"{
+                "devices": [
+                    {
+                        "name": "Sim Earbuds",
+                        "mac_address": "02:00:00:00:00:01",
+                        "start_level": 100,
+                        "phases": [
+                            { "duration_mins": 240, "rate_per_hour": -20 },
+                            { "duration_mins": 60, "rate_per_hour": 80 }
+                        ],
+                        "repeat": true
+                    },
+                    {
+                        "name": "Sim Gamepad",
+                        "mac_address": "02:00:00:00:00:02",
+                        "start_level": 0,
+                        "reports_battery": false
+                    }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sample_scenario_parses() {
+        let scenario = Scenario::from_json(SAMPLE_SCENARIO).unwrap();
+        assert!(!scenario.devices.is_empty());
+        assert!(scenario.time_scale > 1.0);
+    }
+
+    #[test]
+    fn test_invalid_scenarios_are_rejected() {
+        let device = |fields: &str| {
+            format!(
+                r#"{{ "devices": [{{ "name": "Bad", "mac_address": "02:00:00:00:00:09", {} }}] }}"#,
+                fields
+            )
+        };
+        for (json, expected) in [
+            (r#"{ "time_scale": -1, "devices": [] }"#.to_string(), "time_scale"),
+            (r#"{ "time_scale": 0, "devices": [] }"#.to_string(), "time_scale"),
+            (r#"{ "time_scale": 1e300, "devices": [] }"#.to_string(), "time_scale"),
+            (device(r#""start_level": 150"#), "start_level"),
+            (
+                device(r#""start_level": 50, "phases": [{ "duration_mins": -5, "rate_per_hour": 1 }]"#),
+                "duration_mins",
+            ),
+            (
+                device(r#""start_level": 50, "components": [{ "component": "Case", "start_level": 101 }]"#),
+                "start_level",
+            ),
+        ] {
+            let error = format!("{:#}", Scenario::from_json(&json).unwrap_err());
+            assert!(error.contains(expected), "{} gave {}", json, error);
+        }
+    }
+
+    #[test]
+    fn test_level_follows_phases() {
+        let device = &scenario().devices[0];
+        let hours = |h: u64| Duration::from_secs(h * 3600);
+
+        assert_eq!(device.level_at(Duration::ZERO), 100);
+        assert_eq!(device.level_at(hours(2)), 60);
+        assert_eq!(device.level_at(hours(4)), 20);
+        assert_eq!(device.level_at(hours(5)), 100); // charged, clamped
+        assert_eq!(device.level_at(hours(6)), 80); // repeated
+    }
+
+    #[tokio::test]
+    async fn test_chain_reports_simulated_levels() {
+        let chain = SourceChain::new().with_source(MockSource::with_manual_clock(scenario(), 1_700_000_000));
+
+        let devices = chain.discover().await;
+        assert_eq!(devices.len(), 2);
+
+        let reading = chain.read_battery(&devices[0]).await.unwrap();
+        assert_eq!(reading.battery.overall, Some(100));
+        assert_eq!(reading.source, BatteryBackend::Mock);
+        assert_eq!(reading.observed_at, 1_700_000_000);
+
+        assert!(chain.read_battery(&devices[1]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_estimator_converges_on_simulated_drain() {
+        let source = MockSource::with_manual_clock(scenario(), 1_700_000_000);
+        let mut history = BatteryHistory::new();
+
+        for _ in 0..=8 {
+            let devices = source.discover().await.unwrap();
+            let level = devices[0].battery.as_ref().unwrap().overall.unwrap();
+            history.record(BatterySample {
+                timestamp: source.now(),
+                level,
+                source: BatteryBackend::Mock,
+                connection: ConnectionState::Connected,
+                component: BatteryComponent::Overall,
+            });
+            source.advance(Duration::from_secs(15 * 60));
+        }
+
+        // Two hours at 20% per hour: 60% left means three more hours
+        let estimate = history.estimate(60);
+        assert_eq!(estimate.accuracy, Accuracy::Estimated);
+        assert_eq!(estimate.format_remaining(), "3h 0m");
+    }
+}
@@ -0,0 +1,506 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::address::BluetoothAddress;
+use crate::battery_history::{unix_timestamp, BatteryBackend, ConnectionState};
+use crate::bluetooth_battery::BatteryResult;
+use crate::config::Config;
+use crate::device_type::DeviceType;
+use crate::rfcomm_profile::RfcommProfile;
+
+#[cfg(windows)]
+pub mod ble;
+#[cfg(all(target_os = "linux", any(feature = "bluer", test)))]
+pub mod bluez;
+pub mod mock;
+#[cfg(windows)]
+pub mod powershell;
+#[cfg(windows)]
+pub mod rfcomm;
+#[cfg(windows)]
+pub mod uwp;
+
+#[derive(Debug, Clone)]
+pub struct DiscoveredDevice {
+    pub name: String,
+    pub mac_address: String,
+    pub connection: ConnectionState,
+    /// Backend that reported the device.
+    pub source: BatteryBackend,
+    /// Battery reported as part of discovery, if the backend provides it.
+    pub battery: Option<BatteryResult>,
+    /// Class of Device of a Classic device, if the backend reports it.
+    pub class_of_device: Option<u32>,
+    /// GAP Appearance of an LE device, if the backend reports it.
+    pub appearance: Option<u16>,
+}
+
+impl DiscoveredDevice {
+    pub fn device_type(&self) -> DeviceType {
+        DeviceType::classify(self.class_of_device, self.appearance, &self.name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceCapabilities {
+    /// Can enumerate devices.
+    pub discovery: bool,
+    /// Can read the battery of a device discovered by any backend.
+    pub battery: bool,
+    /// Reports left/right/case levels separately.
+    pub components: bool,
+    /// Pushes battery changes through `subscribe`.
+    pub notifications: bool,
+}
+
+/// A way of finding Bluetooth devices and reading their battery.
+#[async_trait]
+pub trait BatterySource: Send + Sync {
+    fn backend(&self) -> BatteryBackend;
+
+    fn capabilities(&self) -> SourceCapabilities;
+
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        Ok(Vec::new())
+    }
+
+    async fn read_battery(&self, _device: &DiscoveredDevice) -> Result<BatteryResult> {
+        Ok(BatteryResult::new())
+    }
+
+    /// Forwards battery changes of `device` to `updates` until the receiver
+    /// is dropped. Returns `false` if the backend cannot notify for it.
+    async fn subscribe(
+        &self,
+        _device: &DiscoveredDevice,
+        _updates: UnboundedSender<BatteryUpdate>,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// The RFCOMM service the last reading of `device` went through, for
+    /// backends that read over one.
+    fn profile(&self, _device: &DiscoveredDevice) -> Option<RfcommProfile> {
+        None
+    }
+
+    /// Timestamp (seconds since the Unix epoch) to record readings with.
+    fn now(&self) -> u64 {
+        unix_timestamp()
+    }
+
+    /// Picks up the settings of a new or reloaded configuration.
+    fn configure(&self, _config: &Config) {}
+}
+
+/// Lets a caller keep a handle on a source it added to a chain, e.g. to
+/// advance a simulated clock.
+#[async_trait]
+impl<T: BatterySource + ?Sized> BatterySource for std::sync::Arc<T> {
+    fn backend(&self) -> BatteryBackend {
+        (**self).backend()
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        (**self).capabilities()
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        (**self).discover().await
+    }
+
+    async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+        (**self).read_battery(device).await
+    }
+
+    async fn subscribe(&self, device: &DiscoveredDevice, updates: UnboundedSender<BatteryUpdate>) -> Result<bool> {
+        (**self).subscribe(device, updates).await
+    }
+
+    fn profile(&self, device: &DiscoveredDevice) -> Option<RfcommProfile> {
+        (**self).profile(device)
+    }
+
+    fn now(&self) -> u64 {
+        (**self).now()
+    }
+
+    fn configure(&self, config: &Config) {
+        (**self).configure(config)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatteryReading {
+    pub battery: BatteryResult,
+    pub source: BatteryBackend,
+    pub connection: ConnectionState,
+    pub observed_at: u64,
+    /// The RFCOMM service (e.g. Hands-Free) the level was read over.
+    pub profile: Option<RfcommProfile>,
+}
+
+/// A battery change pushed by a backend without being polled.
+#[derive(Debug, Clone)]
+pub struct BatteryUpdate {
+    pub mac_address: String,
+    pub reading: BatteryReading,
+}
+
+/// Environment variable naming a scenario file for the simulated backend.
+pub const SCENARIO_ENV: &str = "BT_BATTERY_SCENARIO";
+
+pub fn scenario_path() -> Option<PathBuf> {
+    std::env::var_os(SCENARIO_ENV).map(PathBuf::from)
+}
+
+/// Backends ordered by priority. Discovery uses the first backend that finds
+/// any device; battery reads fall through until one reports a level.
+pub struct SourceChain {
+    /// In the order they were added.
+    sources: Vec<Box<dyn BatterySource>>,
+    /// Indices into `sources` in priority order.
+    order: Mutex<Vec<usize>>,
+}
+
+impl SourceChain {
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            order: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The backends available on this platform, in their default order.
+    pub fn platform_default() -> Self {
+        let chain = Self::new();
+
+        #[cfg(windows)]
+        let chain = chain
+            .with_source(uwp::UwpSource)
+            .with_source(powershell::PowerShellSource)
+            .with_source(rfcomm::RfcommSource::new())
+            .with_source(ble::BleSource);
+
+        #[cfg(all(target_os = "linux", feature = "bluer"))]
+        let chain = chain.with_source(bluez::BluezSource::new(bluez::BluerBus::new()));
+
+        chain
+    }
+
+    /// Simulated devices when `BT_BATTERY_SCENARIO` is set, otherwise the
+    /// platform backends.
+    pub fn configured() -> Self {
+        if let Some(path) = scenario_path() {
+            match mock::MockSource::from_file(&path) {
+                Ok(source) => return Self::new().with_source(source),
+                Err(e) => eprintln!("Failed to load scenario {}: {:#}", path.display(), e),
+            }
+        }
+        Self::platform_default()
+    }
+
+    pub fn with_source(mut self, source: impl BatterySource + 'static) -> Self {
+        self.order.get_mut().unwrap().push(self.sources.len());
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Reorders the chain. Backends not listed keep the order they were added
+    /// in after the listed ones, so an empty `order` restores the default.
+    pub fn set_priority(&self, order: &[BatteryBackend]) {
+        let mut indices: Vec<usize> = (0..self.sources.len()).collect();
+        indices.sort_by_key(|&index| {
+            order
+                .iter()
+                .position(|backend| *backend == self.sources[index].backend())
+                .unwrap_or(order.len())
+        });
+        *self.order.lock().unwrap() = indices;
+    }
+
+    pub fn configure(&self, config: &Config) {
+        for source in &self.sources {
+            source.configure(config);
+        }
+    }
+
+    pub fn backends(&self) -> Vec<BatteryBackend> {
+        self.ordered().iter().map(|source| source.backend()).collect()
+    }
+
+    /// The sources in priority order.
+    fn ordered(&self) -> Vec<&dyn BatterySource> {
+        let order = self.order.lock().unwrap();
+        order.iter().map(|&index| self.sources[index].as_ref()).collect()
+    }
+
+    pub async fn discover(&self) -> Vec<DiscoveredDevice> {
+        for source in self.ordered().into_iter().filter(|s| s.capabilities().discovery) {
+            match source.discover().await {
+                Ok(devices) if !devices.is_empty() => return canonical_addresses(devices),
+                Ok(_) => eprintln!("{:?} discovery found no devices", source.backend()),
+                Err(e) => eprintln!("{:?} discovery failed: {}", source.backend(), e),
+            }
+        }
+        Vec::new()
+    }
+
+    pub async fn read_battery(&self, device: &DiscoveredDevice) -> Option<BatteryReading> {
+        self.read_battery_preferring(device, None).await
+    }
+
+    /// Like `read_battery`, but asks `preferred` first, before even a level
+    /// another backend reported during discovery.
+    pub async fn read_battery_preferring(
+        &self,
+        device: &DiscoveredDevice,
+        preferred: Option<BatteryBackend>,
+    ) -> Option<BatteryReading> {
+        let sources = self.ordered();
+        let preferred = sources.iter().copied().find(|s| {
+            s.capabilities().battery && Some(s.backend()) == preferred && s.backend() != device.source
+        });
+        if let Some(source) = preferred {
+            if let Some(reading) = Self::read_from(source, device).await {
+                return Some(reading);
+            }
+        }
+
+        if let Some(battery) = &device.battery {
+            if battery.get_primary_level().is_some() {
+                let observed_at = sources
+                    .iter()
+                    .find(|s| s.backend() == device.source)
+                    .map_or_else(unix_timestamp, |s| s.now());
+                return Some(BatteryReading {
+                    battery: battery.clone(),
+                    source: device.source,
+                    connection: device.connection,
+                    observed_at,
+                    profile: None,
+                });
+            }
+        }
+
+        for source in sources.iter().copied().filter(|s| s.capabilities().battery) {
+            if preferred.is_some_and(|p| p.backend() == source.backend()) {
+                continue;
+            }
+            if let Some(reading) = Self::read_from(source, device).await {
+                return Some(reading);
+            }
+        }
+        None
+    }
+
+    async fn read_from(source: &dyn BatterySource, device: &DiscoveredDevice) -> Option<BatteryReading> {
+        match source.read_battery(device).await {
+            Ok(battery) if battery.get_primary_level().is_some() => Some(BatteryReading {
+                battery,
+                source: source.backend(),
+                connection: ConnectionState::Connected,
+                observed_at: source.now(),
+                profile: source.profile(device),
+            }),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!(
+                    "{:?} battery query failed for {}: {}",
+                    source.backend(),
+                    device.mac_address,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Subscribes to battery changes with the first backend that can notify
+    /// for `device`. Returns `false` if it has to be polled.
+    pub async fn subscribe(&self, device: &DiscoveredDevice, updates: UnboundedSender<BatteryUpdate>) -> bool {
+        for source in self.ordered().into_iter().filter(|s| s.capabilities().notifications) {
+            match source.subscribe(device, updates.clone()).await {
+                Ok(true) => return true,
+                Ok(false) => {}
+                Err(e) => eprintln!(
+                    "{:?} subscription failed for {}: {}",
+                    source.backend(),
+                    device.mac_address,
+                    e
+                ),
+            }
+        }
+        false
+    }
+}
+
+/// Writes every address in one format, so history and the backends agree on
+/// device identity, and drops devices without a valid address.
+fn canonical_addresses(devices: Vec<DiscoveredDevice>) -> Vec<DiscoveredDevice> {
+    devices
+        .into_iter()
+        .filter_map(|mut device| match device.mac_address.parse::<BluetoothAddress>() {
+            Ok(address) => {
+                device.mac_address = address.to_string();
+                Some(device)
+            }
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", device.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+impl Default for SourceChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedSource {
+        backend: BatteryBackend,
+        devices: Vec<DiscoveredDevice>,
+        level: Option<u8>,
+    }
+
+    #[async_trait]
+    impl BatterySource for FixedSource {
+        fn backend(&self) -> BatteryBackend {
+            self.backend
+        }
+
+        fn capabilities(&self) -> SourceCapabilities {
+            SourceCapabilities {
+                discovery: !self.devices.is_empty(),
+                battery: self.level.is_some(),
+                components: false,
+                notifications: false,
+            }
+        }
+
+        async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+            Ok(self.devices.clone())
+        }
+
+        async fn read_battery(&self, _device: &DiscoveredDevice) -> Result<BatteryResult> {
+            let mut result = BatteryResult::new();
+            result.overall = self.level;
+            Ok(result)
+        }
+
+        fn profile(&self, _device: &DiscoveredDevice) -> Option<RfcommProfile> {
+            (self.backend == BatteryBackend::Rfcomm).then_some(RfcommProfile::Headset)
+        }
+    }
+
+    fn device(name: &str) -> DiscoveredDevice {
+        DiscoveredDevice {
+            name: name.to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            connection: ConnectionState::Unknown,
+            source: BatteryBackend::PowerShell,
+            battery: None,
+            class_of_device: None,
+            appearance: None,
+        }
+    }
+
+    fn source(backend: BatteryBackend, devices: Vec<DiscoveredDevice>, level: Option<u8>) -> FixedSource {
+        FixedSource {
+            backend,
+            devices,
+            level,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_battery_falls_through_chain() {
+        let chain = SourceChain::new()
+            .with_source(source(BatteryBackend::PowerShell, vec![device("Headset")], None))
+            .with_source(source(BatteryBackend::Rfcomm, Vec::new(), None))
+            .with_source(source(BatteryBackend::Ble, Vec::new(), Some(42)));
+
+        let devices = chain.discover().await;
+        assert_eq!(devices.len(), 1);
+
+        let reading = chain.read_battery(&devices[0]).await.unwrap();
+        assert_eq!(reading.battery.overall, Some(42));
+        assert_eq!(reading.source, BatteryBackend::Ble);
+        assert_eq!(reading.connection, ConnectionState::Connected);
+    }
+
+    #[tokio::test]
+    async fn test_preferred_backend_is_asked_first() {
+        let chain = SourceChain::new()
+            .with_source(source(BatteryBackend::PowerShell, vec![device("Headset")], None))
+            .with_source(source(BatteryBackend::Rfcomm, Vec::new(), Some(30)))
+            .with_source(source(BatteryBackend::Ble, Vec::new(), Some(42)));
+        let devices = chain.discover().await;
+
+        let reading = chain.read_battery(&devices[0]).await.unwrap();
+        assert_eq!(reading.source, BatteryBackend::Rfcomm);
+        assert_eq!(reading.profile, Some(RfcommProfile::Headset));
+        let reading = chain
+            .read_battery_preferring(&devices[0], Some(BatteryBackend::Ble))
+            .await
+            .unwrap();
+        assert_eq!(reading.battery.overall, Some(42));
+        assert_eq!(reading.source, BatteryBackend::Ble);
+        assert_eq!(reading.profile, None);
+    }
+
+    #[tokio::test]
+    async fn test_discovery_keeps_only_valid_addresses() {
+        let mut lower_case = device("Headset");
+        lower_case.mac_address = "00-1a-7d-da-71-13".to_string();
+        let mut unknown = device("Radio");
+        unknown.mac_address = String::new();
+
+        let chain = SourceChain::new().with_source(source(BatteryBackend::PowerShell, vec![lower_case, unknown], None));
+        let devices = chain.discover().await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].mac_address, "00:1A:7D:DA:71:13");
+    }
+
+    #[tokio::test]
+    async fn test_priority_reorders_chain() {
+        let chain = SourceChain::new()
+            .with_source(source(BatteryBackend::Rfcomm, Vec::new(), Some(10)))
+            .with_source(source(BatteryBackend::Ble, Vec::new(), Some(20)));
+        chain.set_priority(&[BatteryBackend::Ble]);
+        assert_eq!(chain.backends(), vec![BatteryBackend::Ble, BatteryBackend::Rfcomm]);
+
+        let reading = chain.read_battery(&device("Mouse")).await.unwrap();
+        assert_eq!(reading.battery.overall, Some(20));
+
+        chain.set_priority(&[]);
+        assert_eq!(chain.backends(), vec![BatteryBackend::Rfcomm, BatteryBackend::Ble]);
+    }
+
+    #[tokio::test]
+    async fn test_discovery_battery_is_used_first() {
+        let chain = SourceChain::new().with_source(source(BatteryBackend::Ble, Vec::new(), Some(20)));
+        let mut discovered = device("Keyboard");
+        discovered.source = BatteryBackend::Uwp;
+        discovered.connection = ConnectionState::Disconnected;
+        discovered.battery = Some(BatteryResult {
+            overall: Some(70),
+            left: None,
+            right: None,
+            case: None,
+        });
+
+        let reading = chain.read_battery(&discovered).await.unwrap();
+        assert_eq!(reading.source, BatteryBackend::Uwp);
+        assert_eq!(reading.connection, ConnectionState::Disconnected);
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{BatterySource, DiscoveredDevice, SourceCapabilities};
+use crate::address::BluetoothAddress;
+use crate::battery_history::{BatteryBackend, ConnectionState};
+
+/// Paired Bluetooth devices listed by `Get-PnpDevice`. Discovery only.
+pub struct PowerShellSource;
+
+#[async_trait]
+impl BatterySource for PowerShellSource {
+    fn backend(&self) -> BatteryBackend {
+        BatteryBackend::PowerShell
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            discovery: true,
+            battery: false,
+            components: false,
+            notifications: false,
+        }
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        Ok(get_devices_via_powershell().await)
+    }
+}
+
+/// `DEVPKEY_Bluetooth_ClassOfDevice`, set on Classic devices only.
+const CLASS_OF_DEVICE_KEY: &str = "{2BD67D8B-8BEB-48D5-87E0-6CDA3428040A} 10";
+
+async fn get_devices_via_powershell() -> Vec<DiscoveredDevice> {
+    let mut devices = Vec::new();
+
+    let command = format!(
+        "$OutputEncoding = [Console]::OutputEncoding = [System.Text.Encoding]::UTF8; chcp 65001 | Out-Null; Get-PnpDevice | Where-Object {{ $_.Class -eq 'Bluetooth' -and $_.Status -eq 'OK' }} | Select-Object FriendlyName, InstanceId, @{{ Name = 'ClassOfDevice'; Expression = {{ (Get-PnpDeviceProperty -InstanceId $_.InstanceId -KeyName '{}' -ErrorAction SilentlyContinue).Data }} }} | ConvertTo-Json -Depth 3",
+        CLASS_OF_DEVICE_KEY
+    );
+    let output = std::process::Command::new("powershell")
+        .args(&["-Command", command.as_str()])
+        .output();
+
+    if let Ok(output) = output {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        
+        if let Ok(json_devices) = serde_json::from_str::<serde_json::Value>(&stdout) {
+            let device_array = if json_devices.is_array() {
+                json_devices.as_array().unwrap()
+            } else {
+                &vec![json_devices]
+            };
+
+            for device in device_array {
+                if let (Some(name), Some(instance_id)) = (
+                    device["FriendlyName"].as_str(),
+                    device["InstanceId"].as_str()
+                ) {
+                    // Radios, enumerators and services without an address are not devices
+                    let Some(address) = BluetoothAddress::from_pnp_instance_id(instance_id) else {
+                        continue;
+                    };
+                    let mac_address = address.to_string();
+                    if devices.iter().any(|d: &DiscoveredDevice| d.mac_address == mac_address) {
+                        continue;
+                    }
+
+                    devices.push(DiscoveredDevice {
+                        name: name.to_string(),
+                        mac_address,
+                        connection: ConnectionState::Unknown,
+                        source: BatteryBackend::PowerShell,
+                        battery: None,
+                        class_of_device: device["ClassOfDevice"].as_u64().and_then(|cod| u32::try_from(cod).ok()),
+                        appearance: None,
+                    });
+                }
+            }
+        }
+    }
+    devices
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+use super::{BatterySource, DiscoveredDevice, SourceCapabilities};
+use crate::battery_history::BatteryBackend;
+use crate::bluetooth_battery::BatteryResult;
+use crate::config::Config;
+use crate::device_type::DeviceType;
+use crate::rfcomm_io::RfcommTimeouts;
+use crate::rfcomm_profile::{RfcommProfile, RfcommService, ServiceCache};
+use crate::windows_rfcomm::WindowsRfcommSocket;
+
+/// Classic Bluetooth headsets read over RFCOMM, through whichever of the
+/// Hands-Free, Headset or Serial Port services the device offers. Phones
+/// are read over their Hands-Free Audio Gateway service instead.
+#[derive(Default)]
+pub struct RfcommSource {
+    services: ServiceCache,
+    timeouts: Mutex<RfcommTimeouts>,
+}
+
+impl RfcommSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeouts(timeouts: RfcommTimeouts) -> Self {
+        Self {
+            timeouts: Mutex::new(timeouts),
+            ..Self::default()
+        }
+    }
+
+    pub fn timeouts(&self) -> RfcommTimeouts {
+        *self.timeouts.lock().unwrap()
+    }
+
+    /// The service last used to read a device.
+    pub fn service_for(&self, mac_address: &str) -> Option<RfcommService> {
+        self.services.get(mac_address)
+    }
+
+    /// Reads the battery over the first service that accepts a connection.
+    async fn query(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+        let mut result = BatteryResult::new();
+        let gateway = device.device_type() == DeviceType::Phone;
+        let Some((mut socket, service)) = self.connect(&device.mac_address, gateway).await? else {
+            return Ok(result); // Device might not support RFCOMM
+        };
+        self.services.record(&device.mac_address, service);
+
+        result.overall = if service.profile.is_gateway() {
+            socket.query_gateway_battery().await?
+        } else {
+            socket.query_battery_at_commands().await?
+        };
+        Ok(result)
+    }
+
+    /// Connects to the first service the device accepts, preferring the one
+    /// that worked last time. `None` if the device accepts none of them;
+    /// failing to create a socket at all is an error.
+    async fn connect(&self, mac_address: &str, gateway: bool) -> Result<Option<(WindowsRfcommSocket, RfcommService)>> {
+        let timeouts = self.timeouts();
+        let cached = self.services.get(mac_address);
+        for profile in self.services.candidates(mac_address, gateway) {
+            let channel = cached
+                .filter(|service| service.profile == profile)
+                .and_then(|service| service.channel);
+
+            let mut socket = WindowsRfcommSocket::with_timeouts(timeouts)?;
+            if let Ok(service) = socket.connect_to_service(mac_address, profile, channel).await {
+                return Ok(Some((socket, service)));
+            }
+            if channel.is_some() {
+                // The channel moved; look it up again
+                self.services.forget(mac_address);
+                let mut socket = WindowsRfcommSocket::with_timeouts(timeouts)?;
+                if let Ok(service) = socket.connect_to_service(mac_address, profile, None).await {
+                    return Ok(Some((socket, service)));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl BatterySource for RfcommSource {
+    fn backend(&self) -> BatteryBackend {
+        BatteryBackend::Rfcomm
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            discovery: false,
+            battery: true,
+            components: false,
+            notifications: false,
+        }
+    }
+
+    async fn read_battery(&self, device: &DiscoveredDevice) -> Result<BatteryResult> {
+        // Dropping the query at the deadline closes its socket
+        let session = self.timeouts().session;
+        tokio::time::timeout(session, self.query(device))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("RFCOMM query timed out after {:?}", session)))
+    }
+
+    fn profile(&self, device: &DiscoveredDevice) -> Option<RfcommProfile> {
+        self.service_for(&device.mac_address).map(|service| service.profile)
+    }
+
+    fn configure(&self, config: &Config) {
+        *self.timeouts.lock().unwrap() = config.rfcomm_timeouts();
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{BatterySource, DiscoveredDevice, SourceCapabilities};
+use crate::battery_history::{BatteryBackend, ConnectionState};
+use crate::uwp_bluetooth::get_bluetooth_devices_uwp;
+
+/// Bluetooth LE devices and their GATT Battery Service via WinRT.
+pub struct UwpSource;
+
+#[async_trait]
+impl BatterySource for UwpSource {
+    fn backend(&self) -> BatteryBackend {
+        BatteryBackend::Uwp
+    }
+
+    fn capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities {
+            discovery: true,
+            // Battery is reported as part of discovery
+            battery: false,
+            components: true,
+            notifications: false,
+        }
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredDevice>> {
+        let devices = get_bluetooth_devices_uwp().await?;
+        Ok(devices
+            .into_iter()
+            .map(|(info, battery)| DiscoveredDevice {
+                name: info.name,
+                mac_address: info.mac_address,
+                connection: if info.connected {
+                    ConnectionState::Connected
+                } else {
+                    ConnectionState::Disconnected
+                },
+                source: BatteryBackend::Uwp,
+                battery: battery.get_primary_level().map(|_| battery),
+                class_of_device: None,
+                appearance: info.appearance,
+            })
+            .collect())
+    }
+}
@@ -1,17 +1,20 @@
 use windows::{
     core::*,
-    Devices::Bluetooth::{BluetoothLEDevice, BluetoothUuidHelper},
+    Devices::Bluetooth::{BluetoothConnectionStatus, BluetoothLEDevice, BluetoothUuidHelper},
     Devices::Bluetooth::GenericAttributeProfile::{
-        GattDeviceService, GattCharacteristic, GattClientCharacteristicConfigurationDescriptorValue,
-        GattCommunicationStatus, GattValueChangedEventArgs,
+        GattDeviceService, GattCharacteristic, GattCharacteristicProperties,
+        GattClientCharacteristicConfigurationDescriptorValue, GattCommunicationStatus, GattValueChangedEventArgs,
     },
     Devices::Enumeration::{DeviceInformation, DeviceInformationKind},
     Foundation::{EventRegistrationToken, TypedEventHandler},
     Storage::Streams::DataReader,
 };
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use anyhow::{Result, anyhow};
 
+use crate::bluetooth_battery::{BatteryResult, BatteryServiceInstance, PresentationFormat};
+
 pub struct UwpBluetoothManager {
     devices: HashMap<String, BluetoothLEDevice>,
 }
@@ -54,7 +57,7 @@ impl UwpBluetoothManager {
         Ok(device_ids)
     }
 
-    pub fn get_device_battery(&self, device_id: &str) -> Result<Option<u8>> {
+    pub fn get_device_battery(&self, device_id: &str) -> Result<BatteryResult> {
         if let Some(device) = self.devices.get(device_id) {
             self.query_battery_service(device)
         } else {
@@ -62,78 +65,207 @@ impl UwpBluetoothManager {
         }
     }
 
-    fn query_battery_service(&self, device: &BluetoothLEDevice) -> Result<Option<u8>> {
-        // Battery Service UUID: 0x180F
-        let battery_service_uuid = BluetoothUuidHelper::FromShortId(0x180F)?;
-        
-        // Get GATT services (blocking call)
-        let gatt_async_op = device.GetGattServicesForUuidAsync(battery_service_uuid)?;
-        let gatt_result = gatt_async_op.get()?;
-        
-        if gatt_result.Status()? != GattCommunicationStatus::Success {
-            return Ok(None);
-        }
-        
-        let services = gatt_result.Services()?;
-        if services.Size()? == 0 {
-            return Ok(None);
+    fn query_battery_service(&self, device: &BluetoothLEDevice) -> Result<BatteryResult> {
+        let instances = read_battery_service_instances(device)?;
+        Ok(BatteryResult::from_service_instances(&instances))
+    }
+
+    pub fn get_device_info(&self, device_id: &str) -> Result<Option<UwpDeviceInfo>> {
+        if let Some(device) = self.devices.get(device_id) {
+            Ok(Some(UwpDeviceInfo {
+                name: device.Name()?.to_string(),
+                mac_address: crate::address::BluetoothAddress::from_u64(device.BluetoothAddress()?)?.to_string(),
+                connected: device.ConnectionStatus()? == BluetoothConnectionStatus::Connected,
+                // Unknown (0) when the device does not expose it
+                appearance: device.Appearance().and_then(|appearance| appearance.RawValue()).ok(),
+            }))
+        } else {
+            Ok(None)
         }
-        
-        let battery_service = services.GetAt(0)?;
-        
-        // Battery Level Characteristic UUID: 0x2A19
-        let battery_level_uuid = BluetoothUuidHelper::FromShortId(0x2A19)?;
-        
-        let char_async_op = battery_service.GetCharacteristicsForUuidAsync(battery_level_uuid)?;
-        let char_result = char_async_op.get()?;
-        
+    }
+}
+
+/// Identity of an LE device as WinRT reports it.
+#[derive(Debug, Clone)]
+pub struct UwpDeviceInfo {
+    pub name: String,
+    pub mac_address: String,
+    pub connected: bool,
+    /// GAP Appearance.
+    pub appearance: Option<u16>,
+}
+
+/// Every Battery Level (0x2A19) characteristic of every Battery Service
+/// (0x180F) instance, ordered by attribute handle.
+fn battery_level_characteristics(device: &BluetoothLEDevice) -> Result<Vec<GattCharacteristic>> {
+    // Battery Service UUID: 0x180F
+    let battery_service_uuid = BluetoothUuidHelper::FromShortId(0x180F)?;
+    // Battery Level Characteristic UUID: 0x2A19
+    let battery_level_uuid = BluetoothUuidHelper::FromShortId(0x2A19)?;
+
+    // Get GATT services (blocking call)
+    let gatt_result = device.GetGattServicesForUuidAsync(battery_service_uuid)?.get()?;
+    if gatt_result.Status()? != GattCommunicationStatus::Success {
+        return Ok(Vec::new());
+    }
+
+    let services = gatt_result.Services()?;
+    let mut found = Vec::new();
+    for i in 0..services.Size()? {
+        let service = services.GetAt(i)?;
+        let char_result = service.GetCharacteristicsForUuidAsync(battery_level_uuid)?.get()?;
         if char_result.Status()? != GattCommunicationStatus::Success {
-            return Ok(None);
+            continue;
         }
-        
+
         let characteristics = char_result.Characteristics()?;
-        if characteristics.Size()? == 0 {
-            return Ok(None);
+        for j in 0..characteristics.Size()? {
+            let characteristic = characteristics.GetAt(j)?;
+            found.push((characteristic.AttributeHandle()?, characteristic));
         }
-        
-        let battery_characteristic = characteristics.GetAt(0)?;
-        
-        // Read the battery level (blocking call)
-        let read_async_op = battery_characteristic.ReadValueAsync()?;
-        let read_result = read_async_op.get()?;
-        
-        if read_result.Status()? != GattCommunicationStatus::Success {
-            return Ok(None);
+    }
+
+    found.sort_by_key(|(handle, _)| *handle);
+    Ok(found.into_iter().map(|(_, characteristic)| characteristic).collect())
+}
+
+/// Reads every Battery Service instance of the device with its presentation format.
+pub fn read_battery_service_instances(device: &BluetoothLEDevice) -> Result<Vec<BatteryServiceInstance>> {
+    let mut instances = Vec::new();
+    for characteristic in battery_level_characteristics(device)? {
+        if let Some(level) = read_battery_level(&characteristic)? {
+            instances.push(BatteryServiceInstance {
+                level,
+                format: presentation_format(&characteristic),
+            });
         }
-        
-        let buffer = read_result.Value()?;
-        if buffer.Length()? == 0 {
-            return Ok(None);
+    }
+    Ok(instances)
+}
+
+/// Battery Level notifications enabled by `subscribe_battery_levels`. The
+/// handlers are removed when this is dropped.
+pub struct BatteryNotifications {
+    // Events stop once the device object is released
+    _device: BluetoothLEDevice,
+    registrations: Vec<(GattCharacteristic, EventRegistrationToken)>,
+}
+
+impl Drop for BatteryNotifications {
+    fn drop(&mut self) {
+        for (characteristic, token) in &self.registrations {
+            let _ = characteristic.RemoveValueChanged(*token);
         }
-        
-        let data_reader = DataReader::FromBuffer(&buffer)?;
-        let battery_level = data_reader.ReadByte()?;
-        
-        Ok(Some(battery_level))
     }
+}
+
+/// Enables notifications on every Battery Level characteristic that supports
+/// NOTIFY and calls `on_change` with the levels of all instances whenever one
+/// of them changes. Returns `None` if none of them can notify, in which case
+/// the device has to be polled.
+pub fn subscribe_battery_levels(
+    device: BluetoothLEDevice,
+    on_change: impl Fn(BatteryResult) + Send + Sync + 'static,
+) -> Result<Option<BatteryNotifications>> {
+    let characteristics = battery_level_characteristics(&device)?;
+
+    // Last known level of each instance, updated by the handlers
+    let mut initial = Vec::new();
+    for characteristic in &characteristics {
+        let level = read_battery_level(characteristic).unwrap_or(None);
+        initial.push((level, presentation_format(characteristic)));
+    }
+    let levels = Arc::new(Mutex::new(initial));
+    let on_change = Arc::new(on_change);
 
-    pub fn get_device_info(&self, device_id: &str) -> Result<Option<(String, String)>> {
-        if let Some(device) = self.devices.get(device_id) {
-            let name = device.Name()?.to_string();
-            let address = device.BluetoothAddress()?;
-            let mac_address = format!("{:012X}", address);
-            let formatted_mac = format!("{}:{}:{}:{}:{}:{}",
-                &mac_address[0..2], &mac_address[2..4], &mac_address[4..6],
-                &mac_address[6..8], &mac_address[8..10], &mac_address[10..12]
-            );
-            Ok(Some((name, formatted_mac)))
-        } else {
-            Ok(None)
+    let mut registrations = Vec::new();
+    for (index, characteristic) in characteristics.iter().enumerate() {
+        if !characteristic
+            .CharacteristicProperties()?
+            .contains(GattCharacteristicProperties::Notify)
+        {
+            continue;
+        }
+
+        let status = characteristic
+            .WriteClientCharacteristicConfigurationDescriptorAsync(
+                GattClientCharacteristicConfigurationDescriptorValue::Notify,
+            )?
+            .get()?;
+        if status != GattCommunicationStatus::Success {
+            continue;
         }
+
+        let levels = levels.clone();
+        let on_change = on_change.clone();
+        let handler = TypedEventHandler::new(
+            move |_: &Option<GattCharacteristic>, args: &Option<GattValueChangedEventArgs>| {
+                let Some(args) = args else {
+                    return Ok(());
+                };
+                let buffer = args.CharacteristicValue()?;
+                if buffer.Length()? == 0 {
+                    return Ok(());
+                }
+                let level = DataReader::FromBuffer(&buffer)?.ReadByte()?;
+
+                let battery = {
+                    // A panic here would unwind into WinRT on a thread we do not own
+                    let mut levels = levels.lock().unwrap_or_else(|e| e.into_inner());
+                    levels[index].0 = Some(level);
+                    let instances: Vec<BatteryServiceInstance> = levels
+                        .iter()
+                        .filter_map(|(level, format)| level.map(|level| BatteryServiceInstance { level, format: *format }))
+                        .collect();
+                    BatteryResult::from_service_instances(&instances)
+                };
+                on_change(battery);
+                Ok(())
+            },
+        );
+        let token = characteristic.ValueChanged(&handler)?;
+        registrations.push((characteristic.clone(), token));
+    }
+
+    if registrations.is_empty() {
+        return Ok(None);
     }
+    Ok(Some(BatteryNotifications {
+        _device: device,
+        registrations,
+    }))
 }
 
-pub async fn get_bluetooth_devices_uwp() -> Result<Vec<(String, String, Option<u8>)>> {
+fn read_battery_level(characteristic: &GattCharacteristic) -> Result<Option<u8>> {
+    // Read the battery level (blocking call)
+    let read_result = characteristic.ReadValueAsync()?.get()?;
+    if read_result.Status()? != GattCommunicationStatus::Success {
+        return Ok(None);
+    }
+
+    let buffer = read_result.Value()?;
+    if buffer.Length()? == 0 {
+        return Ok(None);
+    }
+
+    let data_reader = DataReader::FromBuffer(&buffer)?;
+    Ok(Some(data_reader.ReadByte()?))
+}
+
+/// The Characteristic Presentation Format (0x2904) as parsed by Windows.
+fn presentation_format(characteristic: &GattCharacteristic) -> Option<PresentationFormat> {
+    let formats = characteristic.PresentationFormats().ok()?;
+    if formats.Size().ok()? == 0 {
+        return None;
+    }
+    let format = formats.GetAt(0).ok()?;
+    Some(PresentationFormat {
+        namespace: format.Namespace().ok()?,
+        description: format.Description().ok()?,
+    })
+}
+
+pub async fn get_bluetooth_devices_uwp() -> Result<Vec<(UwpDeviceInfo, BatteryResult)>> {
     // Run the blocking operations in a separate thread to avoid blocking the async runtime
     let result = tokio::task::spawn_blocking(|| {
         let mut manager = UwpBluetoothManager::new();
@@ -141,18 +273,14 @@ pub async fn get_bluetooth_devices_uwp() -> Result<Vec<(String, String, Option<u
         let mut devices = Vec::new();
         
         for device_id in device_ids {
-            if let Ok(Some((name, mac_address))) = manager.get_device_info(&device_id) {
-                // Skip empty names or system devices
-                if name.is_empty() || name.starts_with("System") {
-                    continue;
-                }
-                
-                let battery_level = manager.get_device_battery(&device_id).unwrap_or(None);
-                devices.push((name, mac_address, battery_level));
+            // Which devices are shown is up to the configured filter
+            if let Ok(Some(info)) = manager.get_device_info(&device_id) {
+                let battery = manager.get_device_battery(&device_id).unwrap_or_default();
+                devices.push((info, battery));
             }
         }
         
-        Ok::<Vec<(String, String, Option<u8>)>, anyhow::Error>(devices)
+        Ok::<Vec<(UwpDeviceInfo, BatteryResult)>, anyhow::Error>(devices)
     }).await??;
     
     Ok(result)
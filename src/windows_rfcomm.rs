@@ -7,8 +7,15 @@ use windows::Win32::Networking::WinSock::*;
 use windows::Win32::Devices::Bluetooth::*;
 use anyhow;
 
-#[repr(C)]
-#[derive(Debug)]
+use crate::at_commands::parse_mac_address;
+use crate::hfp::gateway::{AudioGateway, GatewayEvent};
+use crate::hfp::{HfpEvent, HfpSession};
+use crate::rfcomm_io::{is_timeout, until_ready, Attempt, RfcommTimeouts};
+use crate::rfcomm_profile::{RfcommProfile, RfcommService};
+
+/// ws2bth.h declares this with 1-byte packing.
+#[repr(C, packed(1))]
+#[derive(Debug, Clone, Copy)]
 struct SOCKADDR_BTH {
     addressFamily: u16,
     btAddr: u64,
@@ -16,13 +23,21 @@ struct SOCKADDR_BTH {
     port: u32,
 }
 
+/// A non-blocking RFCOMM socket. Every call waits asynchronously for the
+/// socket to become ready, up to its timeout, so a silent device never
+/// blocks a Tokio worker; dropping a pending call cancels it.
 pub struct WindowsRfcommSocket {
     socket: Option<SOCKET>,
     connected: bool,
+    timeouts: RfcommTimeouts,
 }
 
 impl WindowsRfcommSocket {
     pub fn new() -> Result<Self, anyhow::Error> {
+        Self::with_timeouts(RfcommTimeouts::default())
+    }
+
+    pub fn with_timeouts(timeouts: RfcommTimeouts) -> Result<Self, anyhow::Error> {
         unsafe {
             // Initialize Winsock
             let mut wsa_data: WSADATA = mem::zeroed();
@@ -35,190 +50,229 @@ impl WindowsRfcommSocket {
             let socket_result = socket(AF_BTH as i32, WINSOCK_SOCKET_TYPE(SOCK_STREAM.0 as i32), BTHPROTO_RFCOMM as i32);
             let socket = match socket_result {
                 Ok(s) => s,
-                Err(_) => return Err(anyhow::anyhow!("Failed to create Bluetooth socket")),
+                Err(_) => {
+                    WSACleanup();
+                    return Err(anyhow::anyhow!("Failed to create Bluetooth socket"));
+                }
             };
 
-            Ok(Self {
+            // Owned from here on, so Drop closes it on any error below
+            let this = Self {
                 socket: Some(socket),
                 connected: false,
-            })
+                timeouts,
+            };
+
+            let mut nonblocking: u32 = 1;
+            if ioctlsocket(socket, FIONBIO, &mut nonblocking) == SOCKET_ERROR {
+                let error = WSAGetLastError();
+                return Err(anyhow::anyhow!("Failed to make socket non-blocking: {:?}", error));
+            }
+
+            Ok(this)
         }
     }
 
-    pub async fn connect_to_device(&mut self, mac_address: &str) -> Result<(), anyhow::Error> {
-        if self.socket.is_none() {
-            return Err(anyhow::anyhow!("Socket not initialized"));
-        }
+    /// Connects to `profile` on the device. With a known `channel` the SDP
+    /// lookup is skipped; otherwise port 0 with the service class UUID has
+    /// Winsock resolve the channel through SDP. Returns the channel used.
+    pub async fn connect_to_service(
+        &mut self,
+        mac_address: &str,
+        profile: RfcommProfile,
+        channel: Option<u8>,
+    ) -> Result<RfcommService, anyhow::Error> {
+        let socket = self.socket.ok_or_else(|| anyhow::anyhow!("Socket not initialized"))?;
 
         // Parse MAC address and connect
-        let mac_bytes = self.parse_mac_address(mac_address)?;
-        
-        unsafe {
+        let mac_bytes = parse_mac_address(mac_address)?;
+
+        let result = unsafe {
             let mut addr: SOCKADDR_BTH = std::mem::zeroed();
             addr.addressFamily = AF_BTH as u16;
             addr.btAddr = mac_bytes;
-            addr.port = 1; // RFCOMM channel 1
-
-            let socket = self.socket.unwrap();
-            let result = connect(socket, &addr as *const _ as *const SOCKADDR, std::mem::size_of::<SOCKADDR_BTH>() as i32);
-            
-            if result == SOCKET_ERROR {
-                let error = WSAGetLastError();
-                return Err(anyhow::anyhow!("Failed to connect to Bluetooth device: {:?}", error));
+            addr.serviceClassId = windows::core::GUID::from_u128(profile.service_class_uuid());
+            addr.port = channel.map_or(0, u32::from);
+
+            connect(socket, &addr as *const _ as *const SOCKADDR, std::mem::size_of::<SOCKADDR_BTH>() as i32)
+        };
+
+        if result == SOCKET_ERROR {
+            let error = unsafe { WSAGetLastError() };
+            let pending = if error == WSAEWOULDBLOCK {
+                // In progress: the socket turns writable once connected
+                until_ready("connect", self.timeouts.connect, || poll(socket, POLLWRNORM)).await
+            } else {
+                Err(anyhow::anyhow!("{:?}", error))
+            };
+            if let Err(e) = pending {
+                return Err(anyhow::anyhow!(
+                    "Failed to connect to {} service of {}: {}",
+                    profile.label(),
+                    mac_address,
+                    e
+                ));
             }
         }
 
         self.connected = true;
-        Ok(())
+        Ok(RfcommService {
+            profile,
+            channel: channel.or_else(|| self.peer_channel()),
+        })
     }
 
-    pub async fn send_data(&self, data: &[u8]) -> Result<(), anyhow::Error> {
-        if !self.connected || self.socket.is_none() {
-            return Err(anyhow::anyhow!("Not connected to device"));
-        }
-
+    /// The channel of the connected peer, which is how we learn what SDP
+    /// resolved to.
+    fn peer_channel(&self) -> Option<u8> {
         unsafe {
-            let socket = self.socket.unwrap();
-            let result = send(socket, data, SEND_RECV_FLAGS(0));
-            
+            let mut peer: SOCKADDR_BTH = std::mem::zeroed();
+            let mut length = std::mem::size_of::<SOCKADDR_BTH>() as i32;
+            let result = getpeername(self.socket?, &mut peer as *mut _ as *mut SOCKADDR, &mut length);
             if result == SOCKET_ERROR {
-                let error = WSAGetLastError();
-                return Err(anyhow::anyhow!("Failed to send data: {:?}", error));
+                return None;
             }
+            u8::try_from(peer.port).ok()
+        }
+    }
+
+    pub async fn send_data(&self, data: &[u8]) -> Result<(), anyhow::Error> {
+        let socket = self.connected_socket()?;
+
+        let mut sent = 0;
+        while sent < data.len() {
+            let remaining = &data[sent..];
+            sent += until_ready("send", self.timeouts.write, || unsafe {
+                let result = send(socket, remaining, SEND_RECV_FLAGS(0));
+                would_block_or(result, "Failed to send data")
+            })
+            .await?;
         }
 
         Ok(())
     }
 
+    /// Waits up to the read timeout for data. Returns 0 once the peer closed
+    /// the connection.
     pub async fn receive_data(&self, buffer: &mut [u8]) -> Result<usize, anyhow::Error> {
-        if !self.connected || self.socket.is_none() {
-            return Err(anyhow::anyhow!("Not connected to device"));
-        }
+        let socket = self.connected_socket()?;
 
-        unsafe {
-            let socket = self.socket.unwrap();
+        until_ready("receive", self.timeouts.read, || unsafe {
             let result = recv(socket, buffer, SEND_RECV_FLAGS(0));
-            
-            if result == SOCKET_ERROR {
-                let error = WSAGetLastError();
-                return Err(anyhow::anyhow!("Failed to receive data: {:?}", error));
-            }
+            would_block_or(result, "Failed to receive data")
+        })
+        .await
+    }
 
-            Ok(result as usize)
+    /// Like `receive_data`, but `None` when the read timeout passes without
+    /// data. Any other failure, e.g. a reset connection, is an error.
+    async fn receive_until_silent(&self, buffer: &mut [u8]) -> Result<Option<usize>, anyhow::Error> {
+        match self.receive_data(buffer).await {
+            Ok(bytes_received) => Ok(Some(bytes_received)),
+            Err(e) if is_timeout(&e) => Ok(None),
+            Err(e) => Err(e),
         }
     }
 
-    pub async fn set_timeout(&self, timeout_ms: u32) -> Result<(), anyhow::Error> {
-        if self.socket.is_none() {
-            return Err(anyhow::anyhow!("Socket not initialized"));
+    fn connected_socket(&self) -> Result<SOCKET, anyhow::Error> {
+        match self.socket {
+            Some(socket) if self.connected => Ok(socket),
+            _ => Err(anyhow::anyhow!("Not connected to device")),
         }
+    }
 
-        unsafe {
-            let socket = self.socket.unwrap();
-            let timeout_bytes = timeout_ms.to_le_bytes();
-            let result = setsockopt(socket, SOL_SOCKET as i32, SO_RCVTIMEO as i32, Some(&timeout_bytes));
-            
-            if result == SOCKET_ERROR {
-                let error = WSAGetLastError();
-                return Err(anyhow::anyhow!("Failed to set socket timeout: {:?}", error));
+    /// Reads a headset's battery by acting as its Audio Gateway: the headset
+    /// sets up the service level connection with its own AT commands and
+    /// then reports battery through `+IPHONEACCEV`, `+BIEV` or `+XEVENT`.
+    /// The socket must be connected with `connect_to_service`.
+    pub async fn query_battery_at_commands(&mut self) -> Result<Option<u8>, anyhow::Error> {
+        let mut gateway = AudioGateway::new();
+        let mut buffer = [0u8; 256];
+        while let Some(bytes_received) = self.receive_until_silent(&mut buffer).await? {
+            if bytes_received == 0 {
+                break;
+            }
+            for event in gateway.feed(&String::from_utf8_lossy(&buffer[..bytes_received])) {
+                if let GatewayEvent::Send(reply) = event {
+                    self.send_data(reply.as_bytes()).await?;
+                }
+            }
+            if gateway.battery().is_some() {
+                break;
             }
         }
 
-        Ok(())
+        Ok(gateway.battery())
     }
 
-    fn parse_mac_address(&self, mac_str: &str) -> Result<u64, anyhow::Error> {
-        let parts: Vec<&str> = mac_str.split(':').collect();
-        if parts.len() != 6 {
-            return Err(anyhow::anyhow!("Invalid MAC address format"));
-        }
-
-        let mut mac_bytes = 0u64;
-        for (i, part) in parts.iter().enumerate() {
-            let byte = u8::from_str_radix(part, 16)?;
-            mac_bytes |= (byte as u64) << (8 * (5 - i));
-        }
-
-        Ok(mac_bytes)
+    /// Reads the `battchg` indicator of an Audio Gateway such as a phone,
+    /// taking the HF role ourselves. The socket must be connected to the
+    /// gateway.
+    pub async fn query_gateway_battery(&mut self) -> Result<Option<u8>, anyhow::Error> {
+        let mut session = HfpSession::new();
+        self.run_commands(&mut session, HfpSession::QUERY_COMMANDS).await?;
+        Ok(session.battery())
     }
 
-    pub async fn query_battery_at_commands(&mut self, mac_address: &str) -> Result<Option<u8>, anyhow::Error> {
-        // Try to connect to the device
-        if let Err(_) = self.connect_to_device(mac_address).await {
-            return Ok(None); // Connection failed, device might not support RFCOMM
+    /// Sends each command and waits for its final result.
+    async fn run_commands(&self, session: &mut HfpSession, commands: &[&str]) -> Result<(), anyhow::Error> {
+        for command in commands {
+            self.send_data(format!("{}\r", command).as_bytes()).await?;
+            self.read_until_final_result(session).await?;
         }
-
-        // Set a reasonable timeout
-        self.set_timeout(5000).await?;
-
-        // Try various AT commands for battery level
-        let at_commands: &[&[u8]] = &[
-            b"AT+CIND?\r\n",
-            b"AT+IPHONEACCEV?\r\n", 
-            b"AT+BRSF=1\r\n",
-            b"AT+CMER=3,0,0,1\r\n",
-        ];
-
-        for command in at_commands {
-            if let Ok(_) = self.send_data(command).await {
-                let mut buffer = [0u8; 256];
-                if let Ok(bytes_received) = self.receive_data(&mut buffer).await {
-                    let response = String::from_utf8_lossy(&buffer[..bytes_received]);
-                    
-                    // Parse battery level from response
-                    if let Some(battery_level) = self.parse_battery_from_response(&response) {
-                        return Ok(Some(battery_level));
-                    }
-                }
-            }
-        }
-
-        Ok(None)
+        Ok(())
     }
 
-    fn parse_battery_from_response(&self, response: &str) -> Option<u8> {
-        // Look for battery indicators in AT command responses
-        if response.contains("+CIND:") {
-            // Parse CIND response for battery level
-            if let Some(start) = response.find("+CIND:") {
-                let values_part = &response[start + 6..];
-                if let Some(end) = values_part.find('\r') {
-                    let values = &values_part[..end];
-                    let parts: Vec<&str> = values.split(',').collect();
-                    // Battery is usually the first or second value
-                    for part in parts.iter().take(3) {
-                        if let Ok(level) = part.trim().parse::<u8>() {
-                            if level <= 100 {
-                                return Some(level);
-                            }
-                        }
-                    }
-                }
+    /// Feeds responses to `session` until the command's OK/ERROR, the peer
+    /// goes silent for the read timeout, or it closes the connection.
+    async fn read_until_final_result(&self, session: &mut HfpSession) -> Result<(), anyhow::Error> {
+        let mut buffer = [0u8; 256];
+        while let Some(bytes_received) = self.receive_until_silent(&mut buffer).await? {
+            if bytes_received == 0 {
+                break;
             }
-        }
-
-        // Look for iPhone accessory protocol battery level
-        if response.contains("IPHONEACCEV") {
-            // Parse iPhone accessory battery level
-            if let Some(start) = response.find("IPHONEACCEV") {
-                let remaining = &response[start..];
-                // Look for battery key-value pairs
-                if remaining.contains("1,") {
-                    // Battery level follows key "1"
-                    if let Some(battery_start) = remaining.find("1,") {
-                        let battery_part = &remaining[battery_start + 2..];
-                        if let Some(comma_pos) = battery_part.find(',') {
-                            let battery_str = &battery_part[..comma_pos];
-                            if let Ok(level) = battery_str.trim().parse::<u8>() {
-                                return Some((level * 10).min(100)); // Convert 0-9 scale to 0-100
-                            }
-                        }
-                    }
-                }
+            let events = session.feed(&String::from_utf8_lossy(&buffer[..bytes_received]));
+            if events.iter().any(|e| matches!(e, HfpEvent::Ok | HfpEvent::Error)) {
+                break;
             }
         }
+        Ok(())
+    }
+}
 
-        None
+/// The byte count of a `send`/`recv`, or `WouldBlock` if the socket is not
+/// ready yet.
+fn would_block_or(result: i32, context: &str) -> Result<Attempt<usize>, anyhow::Error> {
+    if result != SOCKET_ERROR {
+        return Ok(Attempt::Ready(result as usize));
+    }
+    let error = unsafe { WSAGetLastError() };
+    if error == WSAEWOULDBLOCK {
+        Ok(Attempt::WouldBlock)
+    } else {
+        Err(anyhow::anyhow!("{}: {:?}", context, error))
+    }
+}
+
+/// Checks without waiting whether `events` are signalled on the socket.
+fn poll(socket: SOCKET, events: WSAPOLL_EVENT_FLAGS) -> Result<Attempt<()>, anyhow::Error> {
+    let mut fd = WSAPOLLFD {
+        fd: socket,
+        events,
+        revents: WSAPOLL_EVENT_FLAGS::default(),
+    };
+    let result = unsafe { WSAPoll(&mut fd, 1, 0) };
+    if result == SOCKET_ERROR {
+        let error = unsafe { WSAGetLastError() };
+        return Err(anyhow::anyhow!("Failed to poll socket: {:?}", error));
+    }
+    if fd.revents.0 & (POLLERR.0 | POLLHUP.0) != 0 {
+        return Err(anyhow::anyhow!("connection refused"));
+    }
+    if fd.revents.0 & events.0 != 0 {
+        Ok(Attempt::Ready(()))
+    } else {
+        Ok(Attempt::WouldBlock)
     }
 }
 
@@ -232,26 +286,3 @@ impl Drop for WindowsRfcommSocket {
         }
     }
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_mac_parsing() {
-        let socket = WindowsRfcommSocket::new().unwrap();
-        let addr = socket.parse_mac_address("00:11:22:33:44:55").unwrap();
-        assert_eq!(addr, 0x001122334455);
-    }
-
-    #[test]
-    fn test_battery_response_parsing() {
-        let socket = WindowsRfcommSocket::new().unwrap();
-        
-        let response1 = "+IPHONEACCEV: 2,1,5,2,0";
-        assert_eq!(socket.parse_battery_from_response(response1), Some(50));
-        
-        let response2 = "+CIND: 85,1,1,0,0,0,0";
-        assert_eq!(socket.parse_battery_from_response(response2), Some(85));
-    }
-} 
\ No newline at end of file
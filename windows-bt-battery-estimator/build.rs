@@ -1,3 +0,0 @@
-fn main() {
-    slint_build::compile("ui/appwindow.slint").unwrap();
-}